@@ -0,0 +1,218 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::Parser;
+
+use wfc::checks::{RegionSize, RoadNetworks};
+use wfc::learn::learn;
+use wfc::map::{Border, Check, GenerationOptions, Map, Model, generate_map_with_options};
+use wfc::render::{read_map_from_png, render_map_to_png};
+use wfc::save::SavedMap;
+use wfc::tiled::{atlas_path, export_tmj, import_tmj};
+use wfc::tileset::{TileKind, Tileset};
+use wfc::validate::validate_ron;
+
+/// Generates a map without opening a window
+#[derive(Parser)]
+struct Args {
+    /// Map width in tiles
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
+    /// Map height in tiles
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    height: u16,
+    /// Generate the same map every run, random when left out
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Tileset file, its atlas is looked up next to it
+    #[arg(long, default_value = "assets/tiles.tileset.ron")]
+    tileset: PathBuf,
+    /// Tile kind surrounding the whole map, e.g. water for an island
+    #[arg(long)]
+    border: Option<TileKind>,
+    /// Generate maps in the style of this one (a save, .json or .tmj map, or a .png drawn with
+    /// the atlas) instead of matching tile edges
+    #[arg(long)]
+    sample: Option<PathBuf>,
+    /// Width and height of the patterns learned from the sample
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u16).range(1..))]
+    pattern_size: u16,
+    /// Learn patterns that wrap around the edges of the sample, for samples that tile seamlessly
+    #[arg(long, requires = "sample")]
+    periodic: bool,
+    /// Generate parts of the map again until its roads form at most this many networks
+    #[arg(long)]
+    road_networks: Option<usize>,
+    /// Generate parts of the map again until every region of a kind has a size in range, as
+    /// kind:min..max with either bound optional, e.g. water:6.. (repeatable)
+    #[arg(long)]
+    region_size: Vec<RegionSize>,
+    #[arg(long, value_enum, default_value_t = Format::Ascii)]
+    format: Format,
+    /// File to write the map to, standard output when left out (not for png)
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Check the tileset for tiles that don't fit or can't be placed instead of generating
+    #[arg(long)]
+    validate: bool,
+    /// Write a tileset with the neighbours and weights of the tiles in this example map
+    /// (read like --sample) instead of generating
+    #[arg(long, conflicts_with = "validate")]
+    learn: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    /// Same layout as the save files
+    Json,
+    /// One character per tile kind
+    Ascii,
+    Png,
+    /// Tiled map, the atlas path in it is relative to the output file
+    Tmj,
+}
+
+fn main() -> ExitCode {
+    match run(&Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if args.validate {
+        return validate(&args.tileset);
+    }
+    let tileset = Tileset::from_ron(&std::fs::read(&args.tileset)?)?;
+    let atlas = args
+        .tileset
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&tileset.atlas);
+    if let Some(example) = &args.learn {
+        let learned = learn(&tileset, &read_sample(&tileset, &atlas, example)?)?;
+        return write(args, &learned.to_ron()?);
+    }
+    if matches!(args.format, Format::Png) && args.output.is_none() {
+        return Err("png needs an --output file".into());
+    }
+    let model = match &args.sample {
+        Some(sample) => Model::Overlapping {
+            sample: read_sample(&tileset, &atlas, sample)?,
+            size: args.pattern_size as usize,
+            periodic: args.periodic,
+        },
+        None => Model::SimpleTiled,
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    eprintln!("Generating map with seed {}", seed);
+
+    let options = GenerationOptions {
+        width: args.width as usize,
+        height: args.height as usize,
+        seed,
+        border: args.border.map(Border::uniform).unwrap_or_default(),
+        model,
+        checks: checks(args),
+        ..Default::default()
+    };
+    let map = generate_map_with_options(&tileset, &options)?;
+
+    let text = match args.format {
+        Format::Json => {
+            let saved = SavedMap::new(&args.tileset.to_string_lossy(), seed, &map);
+            serde_json::to_string_pretty(&saved)?
+        }
+        Format::Ascii => ascii(&tileset, &map),
+        Format::Tmj => {
+            let image = atlas_path(&atlas, args.output.as_deref())?;
+            export_tmj(&tileset, &map, seed, &image.to_string_lossy())?
+        }
+        Format::Png => {
+            let output = args.output.as_ref().unwrap();
+            render_map_to_png(&tileset, &atlas, &map, output)?;
+            return Ok(());
+        }
+    };
+    write(args, &text)
+}
+
+fn write(args: &Args, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.output {
+        Some(output) => std::fs::write(output, text)?,
+        // not println, which panics when the reader goes away
+        None => writeln!(std::io::stdout(), "{}", text)?,
+    }
+    Ok(())
+}
+
+fn checks(args: &Args) -> Vec<Arc<dyn Check>> {
+    let mut checks: Vec<Arc<dyn Check>> = vec![];
+    if let Some(max) = args.road_networks {
+        checks.push(Arc::new(RoadNetworks { max }));
+    }
+    for size in &args.region_size {
+        checks.push(Arc::new(size.clone()));
+    }
+    checks
+}
+
+// Loads the map to learn from by its extension, anything but png, tmj and json is read as a
+// save
+fn read_sample(
+    tileset: &Tileset,
+    atlas: &Path,
+    sample: &Path,
+) -> Result<Map, Box<dyn std::error::Error>> {
+    let extension = sample.extension().and_then(|extension| extension.to_str());
+    Ok(match extension {
+        Some("png") => read_map_from_png(tileset, atlas, sample)?,
+        Some("tmj") => import_tmj(&std::fs::read_to_string(sample)?, tileset)?.0,
+        // what --format json writes
+        Some("json") => SavedMap::from_json(&std::fs::read_to_string(sample)?)?.map(tileset)?,
+        _ => SavedMap::load(sample)?.map(tileset)?,
+    })
+}
+
+// Prints every issue of the tileset, fails if any of them is an error
+fn validate(tileset: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let issues = validate_ron(&std::fs::read(tileset)?)?;
+    let mut stdout = std::io::stdout();
+    for issue in &issues {
+        let level = if issue.is_error() { "error" } else { "warning" };
+        writeln!(stdout, "{}: {}", level, issue)?;
+    }
+    let errors = issues.iter().filter(|issue| issue.is_error()).count();
+    if errors > 0 {
+        return Err(format!("{} has {} errors", tileset.display(), errors).into());
+    }
+    writeln!(stdout, "{} is valid", tileset.display())?;
+    Ok(())
+}
+
+// Rows from top to bottom
+fn ascii(tileset: &Tileset, map: &Map) -> String {
+    (0..map.height())
+        .rev()
+        .map(|y| {
+            (0..map.width())
+                .map(|x| match tileset.get(map[(x, y)]).map(|tile| tile.kind) {
+                    Some(TileKind::Water) => '~',
+                    Some(TileKind::Grass) => '.',
+                    Some(TileKind::Forest) => '^',
+                    Some(TileKind::Road) => '=',
+                    Some(TileKind::Crossroad) => '+',
+                    Some(TileKind::Roadturn) => '/',
+                    Some(TileKind::Roadend) => 'o',
+                    None => '?',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
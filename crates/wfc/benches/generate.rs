@@ -0,0 +1,25 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use wfc::map::generate_map_with_seed;
+use wfc::tileset::Tileset;
+
+fn load_tileset() -> Tileset {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../assets/tiles.tileset.ron");
+    Tileset::from_ron(&std::fs::read(path).unwrap()).unwrap()
+}
+
+fn generate(c: &mut Criterion) {
+    let tileset = load_tileset();
+
+    let mut group = c.benchmark_group("generate_map");
+    group.sample_size(10);
+    for size in [50, 100, 200] {
+        group.bench_function(format!("{}x{}", size, size), |b| {
+            b.iter(|| generate_map_with_seed(&tileset, size, size, 1).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, generate);
+criterion_main!(benches);
@@ -0,0 +1,208 @@
+use crate::map::{Check, DIRECTIONS, Direction, Map};
+use crate::tileset::{TileKind, Tileset};
+
+// Roads form at most this many separate networks, the smallest one is generated again while
+// there are more. Loops that lead nowhere count as networks of their own.
+pub struct RoadNetworks {
+    pub max: usize,
+}
+
+impl Check for RoadNetworks {
+    fn violation(&self, tileset: &Tileset, map: &Map) -> Option<Vec<(usize, usize)>> {
+        // where a tile has a road edge, as the cell draws it
+        let road_edge = |x: usize, y: usize, direction: Direction| {
+            tileset.get(map[(x, y)]).is_some_and(|tile| {
+                direction.edge(&tile.oriented(map.orientation(x, y)))
+                    == (TileKind::Road, TileKind::Road)
+            })
+        };
+        let is_road = |x: usize, y: usize| {
+            tileset.get(map[(x, y)]).is_some_and(|tile| {
+                matches!(
+                    tile.kind,
+                    TileKind::Road | TileKind::Roadturn | TileKind::Crossroad | TileKind::Roadend
+                )
+            })
+        };
+        let networks = regions(map, is_road, |(x, y), direction, (other_x, other_y)| {
+            road_edge(x, y, direction) && road_edge(other_x, other_y, direction.opposite())
+        });
+        if networks.len() <= self.max {
+            return None;
+        }
+        networks.into_iter().min_by_key(|network| network.len())
+    }
+}
+
+// Connected tiles of a kind, e.g. the water of a lake, cover at least min and at most max cells.
+// The first region that doesn't is generated again.
+#[derive(Clone, Debug)]
+pub struct RegionSize {
+    pub kind: TileKind,
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Check for RegionSize {
+    fn violation(&self, tileset: &Tileset, map: &Map) -> Option<Vec<(usize, usize)>> {
+        let is_kind = |x: usize, y: usize| {
+            tileset
+                .get(map[(x, y)])
+                .is_some_and(|tile| tile.kind == self.kind)
+        };
+        regions(map, is_kind, |_, _, _| true)
+            .into_iter()
+            .find(|region| !(self.min..=self.max).contains(&region.len()))
+    }
+}
+
+// kind:min..max, either bound may be left out, e.g. water:6.. or forest:..200
+impl std::str::FromStr for RegionSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<RegionSize, String> {
+        let Some((kind, (min, max))) = text
+            .split_once(':')
+            .and_then(|(kind, range)| Some((kind, range.split_once("..")?)))
+        else {
+            return Err(format!("expected kind:min..max, got {}", text));
+        };
+        let bound = |bound: &str, default| match bound {
+            "" => Ok(default),
+            bound => bound
+                .parse()
+                .map_err(|_| format!("invalid region size {}", bound)),
+        };
+        let (min, max) = (bound(min, 0)?, bound(max, usize::MAX)?);
+        if min > max {
+            return Err(format!("region size {} is above {}", min, max));
+        }
+        Ok(RegionSize {
+            kind: kind.parse()?,
+            min,
+            max,
+        })
+    }
+}
+
+// Groups of member cells reached from each other through joined neighbours, found row by row
+// from the bottom
+pub(crate) fn regions(
+    map: &Map,
+    member: impl Fn(usize, usize) -> bool,
+    joined: impl Fn((usize, usize), Direction, (usize, usize)) -> bool,
+) -> Vec<Vec<(usize, usize)>> {
+    let mut seen = vec![false; map.width() * map.height()];
+    let mut regions = vec![];
+    for (x, y, _) in map.iter() {
+        if seen[y * map.width() + x] || !member(x, y) {
+            continue;
+        }
+        seen[y * map.width() + x] = true;
+        let mut region = vec![];
+        let mut stack = vec![(x, y)];
+        while let Some(cell) = stack.pop() {
+            region.push(cell);
+            for direction in DIRECTIONS {
+                let Some((x, y)) = map.neighbour(cell.0, cell.1, direction) else {
+                    continue;
+                };
+                if !seen[y * map.width() + x] && member(x, y) && joined(cell, direction, (x, y)) {
+                    seen[y * map.width() + x] = true;
+                    stack.push((x, y));
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::map::{GenerationOptions, generate_map_with_options};
+    use crate::tileset::load_tileset;
+
+    #[test]
+    fn finds_the_smallest_network() {
+        // forest, a road two tiles long, forest, and a road one tile long
+        let map =
+            Map::from_columns(vec![vec![11], vec![35], vec![35], vec![11], vec![35]]).unwrap();
+        let tileset = load_tileset();
+        assert_eq!(
+            RoadNetworks { max: 1 }.violation(&tileset, &map),
+            Some(vec![(4, 0)])
+        );
+        assert_eq!(RoadNetworks { max: 2 }.violation(&tileset, &map), None);
+    }
+
+    #[test]
+    fn finds_regions_of_the_wrong_size() {
+        // two forest tiles, grass, one forest tile
+        let map = Map::from_columns(vec![vec![11, 11], vec![70, 70], vec![11, 70]]).unwrap();
+        let tileset = load_tileset();
+        let forest = |min, max| RegionSize {
+            kind: TileKind::Forest,
+            min,
+            max,
+        };
+        assert_eq!(forest(2, 10).violation(&tileset, &map), Some(vec![(2, 0)]));
+        assert_eq!(
+            forest(0, 1).violation(&tileset, &map),
+            Some(vec![(0, 0), (0, 1)])
+        );
+        assert_eq!(forest(1, 2).violation(&tileset, &map), None);
+    }
+
+    #[test]
+    fn parses_region_sizes() {
+        let size = "water:6..".parse::<RegionSize>().unwrap();
+        assert_eq!(
+            (size.kind, size.min, size.max),
+            (TileKind::Water, 6, usize::MAX)
+        );
+        let size = "Forest:..200".parse::<RegionSize>().unwrap();
+        assert_eq!((size.kind, size.min, size.max), (TileKind::Forest, 0, 200));
+        assert!("water:6".parse::<RegionSize>().is_err());
+        assert!("lava:1..2".parse::<RegionSize>().is_err());
+        assert!("water:10..5".parse::<RegionSize>().is_err());
+        assert!("water:5..5".parse::<RegionSize>().is_ok());
+    }
+
+    #[test]
+    fn repairs_until_roads_connect() {
+        let tileset = load_tileset();
+        let check = RoadNetworks { max: 1 };
+        let options = GenerationOptions {
+            width: 30,
+            height: 30,
+            seed: 2,
+            checks: vec![Arc::new(RoadNetworks { max: 1 })],
+            ..Default::default()
+        };
+        let map = generate_map_with_options(&tileset, &options).unwrap();
+        assert_eq!(check.violation(&tileset, &map), None);
+        // the map as first generated had more networks
+        assert!(options.progress.repairs() > 0);
+    }
+
+    #[test]
+    fn repairs_until_regions_fit() {
+        let tileset = load_tileset();
+        let check = "forest:..6".parse::<RegionSize>().unwrap();
+        let options = GenerationOptions {
+            width: 20,
+            height: 20,
+            seed: 1,
+            checks: vec![Arc::new(check.clone())],
+            ..Default::default()
+        };
+        let map = generate_map_with_options(&tileset, &options).unwrap();
+        assert_eq!(check.violation(&tileset, &map), None);
+        // the map as first generated had bigger forests
+        assert!(options.progress.repairs() > 0);
+    }
+}
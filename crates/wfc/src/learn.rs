@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::map::{DIRECTIONS, Map};
+use crate::tileset::{Neighbour, Sides, Tileset};
+
+#[derive(Debug)]
+pub enum LearnError {
+    UnknownTile { x: usize, y: usize, index: usize },
+    // The cell is turned or mirrored in a way the symmetry of its tile doesn't allow
+    WrongOrientation { x: usize, y: usize },
+}
+
+impl std::fmt::Display for LearnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LearnError::UnknownTile { x, y, index } => {
+                write!(f, "tile {} at ({}, {}) is not in the tileset", index, x, y)
+            }
+            LearnError::WrongOrientation { x, y } => write!(
+                f,
+                "tile at ({}, {}) is turned or mirrored in a way its tile can't be",
+                x, y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LearnError {}
+
+// Learns from an example map which variants sit next to each other and how often each tile is
+// used. The tiles of the returned tileset are the ones in the example, keeping their edges and
+// symmetry, with weights following their frequency and neighbour lists of every variant seen
+// on each side.
+pub fn learn(tileset: &Tileset, example: &Map) -> Result<Tileset, LearnError> {
+    let mut counts = BTreeMap::<usize, usize>::new();
+    let mut seen = BTreeMap::<usize, [BTreeSet<Neighbour>; 4]>::new();
+    for (x, y, index) in example.iter() {
+        let Some(tile) = tileset.get(index) else {
+            return Err(LearnError::UnknownTile { x, y, index });
+        };
+        let orientation = example.orientation(x, y);
+        if tile.variant(orientation).is_none() {
+            return Err(LearnError::WrongOrientation { x, y });
+        }
+        *counts.entry(index).or_default() += 1;
+
+        // undo the orientation of the cell, the lists are of the tile as drawn
+        let undo = orientation.inverse();
+        let sides = seen.entry(index).or_default();
+        for direction in DIRECTIONS {
+            let Some((x, y)) = example.neighbour(x, y, direction) else {
+                continue;
+            };
+            // the neighbour reports its own problems when its turn comes
+            let other = example[(x, y)];
+            let Some(other_tile) = tileset.get(other) else {
+                continue;
+            };
+            // a neighbour that can't be drawn this way still fits the tile turned back
+            let drawn = example.orientation(x, y).then(undo);
+            sides[direction.oriented(undo) as usize].insert(Neighbour {
+                index: other,
+                orientation: other_tile.variant(drawn).unwrap_or(drawn),
+            });
+        }
+    }
+
+    let tiles = counts.into_iter().map(|(index, count)| {
+        let mut tile = tileset.get(index).unwrap().clone();
+        // the weight is for each variant
+        tile.weight = count as f32 / tile.orientations().len() as f32;
+        let [top, bottom, left, right] = seen.remove(&index).unwrap_or_default();
+        tile.neighbours = Some(Sides {
+            top: top.into_iter().collect(),
+            bottom: bottom.into_iter().collect(),
+            left: left.into_iter().collect(),
+            right: right.into_iter().collect(),
+        });
+        tile
+    });
+    Ok(tileset.with_tiles(tiles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tileset::{Orientation, plain_tile, test_tileset};
+
+    // Grass, a straight road and water
+    fn tileset() -> Tileset {
+        test_tileset(&[
+            plain_tile(0, "Grass", ""),
+            "(index: 1, kind: Road, weight: 1.0, top: (Grass, Grass), bottom: (Grass, Grass), \
+             left: (Road, Road), right: (Road, Road), symmetry: Some(I))"
+                .to_string(),
+            "(index: 2, kind: Water, weight: 1.0, top: (Water, Water), bottom: (Water, Water), \
+             left: (Water, Water), right: (Water, Water))"
+                .to_string(),
+        ])
+    }
+
+    #[test]
+    fn learns_neighbours_and_weights() {
+        // 0 1 1
+        // 0 0 0
+        let example = Map::from_columns(vec![vec![0, 0], vec![0, 1], vec![0, 1]]).unwrap();
+        let learned = learn(&tileset(), &example).unwrap();
+
+        assert!(learned.get(2).is_none());
+        let grass = learned.get(0).unwrap();
+        let road = learned.get(1).unwrap();
+        assert_eq!(grass.weight, 4.0);
+        // seen twice, split between the two variants
+        assert_eq!(road.weight, 1.0);
+        let neighbours = road.neighbours.as_ref().unwrap();
+        let as_drawn = |index| Neighbour {
+            index,
+            orientation: Orientation::default(),
+        };
+        assert_eq!(neighbours.left, vec![as_drawn(0), as_drawn(1)]);
+        assert_eq!(neighbours.right, vec![as_drawn(1)]);
+        assert_eq!(neighbours.top, vec![]);
+        assert_eq!(neighbours.bottom, vec![as_drawn(0)]);
+    }
+
+    #[test]
+    fn learns_turned_tiles_as_drawn() {
+        // a road going up with grass on its left
+        let mut example = Map::from_columns(vec![vec![0], vec![1]]).unwrap();
+        example.set_orientation(1, 0, Orientation::new(1, false));
+        let learned = learn(&tileset(), &example).unwrap();
+
+        // turned back the grass is below the road, and turned the other way
+        let neighbours = learned.get(1).unwrap().neighbours.clone().unwrap();
+        let grass = Neighbour {
+            index: 0,
+            orientation: Orientation::new(3, false),
+        };
+        assert_eq!(neighbours.bottom, vec![grass]);
+
+        // so it fits left of the road turned again
+        let turned = learned.get(1).unwrap().oriented(Orientation::new(1, false));
+        assert_eq!(
+            turned.neighbours.unwrap().left[0].orientation,
+            Orientation::default()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_tiles() {
+        let example = Map::from_columns(vec![vec![0, 7]]).unwrap();
+        assert!(matches!(
+            learn(&tileset(), &example),
+            Err(LearnError::UnknownTile {
+                x: 0,
+                y: 1,
+                index: 7
+            })
+        ));
+    }
+}
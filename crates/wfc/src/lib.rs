@@ -0,0 +1,8 @@
+pub mod checks;
+pub mod learn;
+pub mod map;
+pub mod render;
+pub mod save;
+pub mod tiled;
+pub mod tileset;
+pub mod validate;
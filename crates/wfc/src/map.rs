@@ -0,0 +1,1926 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicUsize};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::tileset::{Orientation, Tile, TileKind, Tileset};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+use Direction::*;
+
+pub(crate) const DIRECTIONS: [Direction; 4] = [Top, Bottom, Left, Right];
+
+impl Direction {
+    pub(crate) fn opposite(self) -> Direction {
+        match self {
+            Top => Bottom,
+            Bottom => Top,
+            Left => Right,
+            Right => Left,
+        }
+    }
+
+    pub(crate) fn edge(self, tile: &Tile) -> (TileKind, TileKind) {
+        match self {
+            Top => tile.top,
+            Bottom => tile.bottom,
+            Left => tile.left,
+            Right => tile.right,
+        }
+    }
+
+    // Where this side of a tile ends up once the orientation is applied
+    pub(crate) fn oriented(self, orientation: Orientation) -> Direction {
+        let mut direction = match (self, orientation.mirrored) {
+            (Left, true) => Right,
+            (Right, true) => Left,
+            (direction, _) => direction,
+        };
+        for _ in 0..orientation.turns {
+            direction = match direction {
+                Top => Right,
+                Right => Bottom,
+                Bottom => Left,
+                Left => Top,
+            };
+        }
+        direction
+    }
+
+    // Whether the variant other may sit on this side of the variant tile, both already oriented.
+    // Neighbour lists decide when either tile has them, the edges otherwise, and the allow and
+    // deny lists of both have to agree.
+    pub(crate) fn fits(
+        self,
+        (tile, orientation): (&Tile, Orientation),
+        (other, other_orientation): (&Tile, Orientation),
+    ) -> bool {
+        let names = |tile: &Tile, direction: Direction, other: &Tile, orientation| {
+            tile.neighbours.as_ref().is_none_or(|neighbours| {
+                neighbours.side(direction).iter().any(|neighbour| {
+                    neighbour.index == other.index
+                        && other.variant(neighbour.orientation) == Some(orientation)
+                })
+            })
+        };
+        let fits = if tile.neighbours.is_none() && other.neighbours.is_none() {
+            self.edge(tile) == self.opposite().edge(other)
+        } else {
+            names(tile, self, other, other_orientation)
+                && names(other, self.opposite(), tile, orientation)
+        };
+        let allows = |tile: &Tile, direction: Direction, other: &Tile| {
+            let allow = tile.allow.side(direction);
+            (allow.is_empty() || allow.iter().any(|adjacent| adjacent.matches(other)))
+                && !tile
+                    .deny
+                    .side(direction)
+                    .iter()
+                    .any(|adjacent| adjacent.matches(other))
+        };
+        fits && allows(tile, self, other) && allows(other, self.opposite(), tile)
+    }
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Top => write!(f, "top"),
+            Bottom => write!(f, "bottom"),
+            Left => write!(f, "left"),
+            Right => write!(f, "right"),
+        }
+    }
+}
+
+// What the solver knows about a cell when picking the next one to collapse
+pub struct CellEntropy {
+    pub x: usize,
+    pub y: usize,
+    // Position of the cell in row by row scan order
+    pub index: usize,
+    // Tiles the cell can still become
+    pub count: usize,
+    pub weight_sum: f64,
+    // Sum of weight * ln(weight) over the tiles the cell can still become
+    pub weight_log_weight_sum: f64,
+}
+
+// Decides which cell collapses next, the lowest value goes first.
+// Ties are broken at random by the solver.
+pub trait Heuristic: Send + Sync {
+    fn entropy(&self, cell: &CellEntropy) -> f64;
+}
+
+// A rule about the whole finished map the solver can't enforce one cell at a time, e.g. roads
+// being connected. generate_map_with_options generates the cells around a violation again
+// until every check holds, stepping a Generator leaves them out.
+pub trait Check: Send + Sync {
+    // Cells of one place where the map breaks the rule, None when it holds
+    fn violation(&self, tileset: &Tileset, map: &Map) -> Option<Vec<(usize, usize)>>;
+}
+
+// Fewest tiles left first
+pub struct MinCount;
+
+impl Heuristic for MinCount {
+    fn entropy(&self, cell: &CellEntropy) -> f64 {
+        cell.count as f64
+    }
+}
+
+// Shannon entropy of the tiles left, weighted by how likely each one is to be picked
+pub struct Shannon;
+
+impl Heuristic for Shannon {
+    fn entropy(&self, cell: &CellEntropy) -> f64 {
+        if cell.weight_sum <= 0.0 {
+            // only zero weight tiles left, they are picked uniformly
+            return (cell.count as f64).ln();
+        }
+        cell.weight_sum.ln() - cell.weight_log_weight_sum / cell.weight_sum
+    }
+}
+
+// Row by row from the bottom left, ignoring the domains
+pub struct Scanline;
+
+impl Heuristic for Scanline {
+    fn entropy(&self, cell: &CellEntropy) -> f64 {
+        cell.index as f64
+    }
+}
+
+// Cells of the map a constraint applies to
+#[derive(Clone, Debug)]
+pub enum Region {
+    Cell { x: usize, y: usize },
+    Rect { x: usize, y: usize, width: usize, height: usize },
+    Column(usize),
+    Row(usize),
+}
+
+impl Region {
+    fn cells(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        match *self {
+            Region::Cell { x, y } => vec![(x, y)],
+            Region::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => (x..x + width)
+                .flat_map(|x| (y..y + height).map(move |y| (x, y)))
+                .collect(),
+            Region::Column(x) => (0..height).map(|y| (x, y)).collect(),
+            Region::Row(y) => (0..width).map(|x| (x, y)).collect(),
+        }
+    }
+}
+
+// What the cells of a region may become
+#[derive(Clone, Debug)]
+pub enum Allowed {
+    Kind(TileKind),
+    // Atlas indices
+    Tiles(Vec<usize>),
+}
+
+// Restricts a region of the map before generation starts,
+// e.g. tile (10, 10) must be a Crossroad or column 0 must be Water
+#[derive(Clone, Debug)]
+pub struct Constraint {
+    pub region: Region,
+    pub allowed: Allowed,
+}
+
+// What lies beyond each side of the map. Tiles on a side with a kind must have an edge made
+// only of that kind facing out, so Border::uniform(Water) makes islands. None leaves the
+// side unconstrained.
+#[derive(Default, Clone, Copy)]
+pub struct Border {
+    pub top: Option<TileKind>,
+    pub bottom: Option<TileKind>,
+    pub left: Option<TileKind>,
+    pub right: Option<TileKind>,
+}
+
+impl Border {
+    pub fn uniform(kind: TileKind) -> Border {
+        Border {
+            top: Some(kind),
+            bottom: Some(kind),
+            left: Some(kind),
+            right: Some(kind),
+        }
+    }
+
+    fn side(&self, direction: Direction) -> Option<TileKind> {
+        match direction {
+            Top => self.top,
+            Bottom => self.bottom,
+            Left => self.left,
+            Right => self.right,
+        }
+    }
+}
+
+// Tiles already placed just beyond each side of the map, e.g. the edges of neighbouring
+// chunks, as atlas indices and the orientation of their variant. Top and bottom are indexed
+// by x, left and right by y. Cells next to one must fit it, cells past the end of a side are
+// left unconstrained.
+#[derive(Default, Clone, Debug)]
+pub struct Surroundings {
+    pub top: Vec<(usize, Orientation)>,
+    pub bottom: Vec<(usize, Orientation)>,
+    pub left: Vec<(usize, Orientation)>,
+    pub right: Vec<(usize, Orientation)>,
+}
+
+impl Surroundings {
+    fn side(&self, direction: Direction) -> &[(usize, Orientation)] {
+        match direction {
+            Top => &self.top,
+            Bottom => &self.bottom,
+            Left => &self.left,
+            Right => &self.right,
+        }
+    }
+}
+
+// Collapsed cells out of the total, can be read from another thread while generating.
+// Repairs count the regions generated again once every cell is collapsed.
+#[derive(Clone, Default)]
+pub struct Progress {
+    collapsed: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+    repairs: Arc<AtomicUsize>,
+}
+
+impl Progress {
+    pub fn collapsed(&self) -> usize {
+        self.collapsed.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn repairs(&self) -> usize {
+        self.repairs.load(atomic::Ordering::Relaxed)
+    }
+
+    // Between 0 and 1
+    pub fn fraction(&self) -> f32 {
+        match self.total() {
+            0 => 0.0,
+            total => self.collapsed() as f32 / total as f32,
+        }
+    }
+
+    fn set(&self, collapsed: usize, total: usize) {
+        self.collapsed.store(collapsed, atomic::Ordering::Relaxed);
+        self.total.store(total, atomic::Ordering::Relaxed);
+    }
+
+    fn set_repairs(&self, repairs: usize) {
+        self.repairs.store(repairs, atomic::Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+pub struct GenerationOptions {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    // How many times the solver may roll back a decision before giving up
+    pub max_backtracks: usize,
+    pub heuristic: Arc<dyn Heuristic>,
+    pub constraints: Vec<Constraint>,
+    pub border: Border,
+    pub surroundings: Surroundings,
+    // Updated as cells collapse
+    pub progress: Progress,
+    pub model: Model,
+    pub checks: Vec<Arc<dyn Check>>,
+    // How many times a violation of the checks may be generated again before giving up
+    pub max_repairs: usize,
+}
+
+// Where the solver gets its rules from
+#[derive(Clone, Default)]
+pub enum Model {
+    // Tiles fit next to each other when their edges match
+    #[default]
+    SimpleTiled,
+    // Learns the size x size patterns of the sample and which overlap, every cell of the map is
+    // the bottom left corner of one of them. Patterns are picked as often as they appear. Only
+    // patterns inside the sample are learned unless it's periodic, when it wraps around and its
+    // opposite sides should fit together like those of a seamless texture.
+    Overlapping {
+        sample: Map,
+        size: usize,
+        periodic: bool,
+    },
+}
+
+impl Default for GenerationOptions {
+    fn default() -> Self {
+        GenerationOptions {
+            width: 50,
+            height: 50,
+            seed: 0,
+            max_backtracks: 1000,
+            heuristic: Arc::new(Shannon),
+            constraints: vec![],
+            border: Border::default(),
+            surroundings: Surroundings::default(),
+            progress: Progress::default(),
+            model: Model::default(),
+            checks: vec![],
+            max_repairs: 100,
+        }
+    }
+}
+
+// A generated map, the atlas index of every cell and the orientation it is drawn with.
+// (0, 0) is the bottom left corner.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Map {
+    width: usize,
+    height: usize,
+    // Row by row from the bottom
+    tiles: Vec<usize>,
+    orientations: Vec<Orientation>,
+}
+
+impl Map {
+    // Columns indexed [x][y], None if they don't all have the same length. Every tile is drawn
+    // as is until given an orientation.
+    pub fn from_columns(columns: Vec<Vec<usize>>) -> Option<Map> {
+        let width = columns.len();
+        let height = columns.first().map_or(0, |column| column.len());
+        if columns.iter().any(|column| column.len() != height) {
+            return None;
+        }
+        let tiles = (0..height)
+            .flat_map(|y| columns.iter().map(move |column| column[y]))
+            .collect();
+        Some(Map {
+            width,
+            height,
+            tiles,
+            orientations: vec![Orientation::default(); width * height],
+        })
+    }
+
+    // Columns indexed [x][y]
+    pub fn columns(&self) -> Vec<Vec<usize>> {
+        (0..self.width)
+            .map(|x| (0..self.height).map(|y| self[(x, y)]).collect())
+            .collect()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(self.tiles[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn orientation(&self, x: usize, y: usize) -> Orientation {
+        self.orientations[self.cell(x, y)]
+    }
+
+    pub fn set_orientation(&mut self, x: usize, y: usize, orientation: Orientation) {
+        let cell = self.cell(x, y);
+        self.orientations[cell] = orientation;
+    }
+
+    // (x, y, atlas index) of every cell, row by row from the bottom
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(|(i, &index)| (i % self.width, i / self.width, index))
+    }
+
+    // The cell next to (x, y) in that direction, None past the edge of the map
+    pub fn neighbour(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        match direction {
+            Top => (y + 1 < self.height).then_some((x, y + 1)),
+            Bottom => y.checked_sub(1).map(|y| (x, y)),
+            Left => x.checked_sub(1).map(|x| (x, y)),
+            Right => (x + 1 < self.width).then_some((x + 1, y)),
+        }
+    }
+
+    fn cell(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "({}, {}) is outside the map",
+            x,
+            y
+        );
+        y * self.width + x
+    }
+}
+
+impl std::ops::Index<(usize, usize)> for Map {
+    type Output = usize;
+
+    fn index(&self, (x, y): (usize, usize)) -> &usize {
+        &self.tiles[self.cell(x, y)]
+    }
+}
+
+// Atlas indices the neighbours of a cell could still become when it ran out of tiles,
+// None past the edge of the map
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Neighbours {
+    pub top: Option<Vec<usize>>,
+    pub bottom: Option<Vec<usize>>,
+    pub left: Option<Vec<usize>>,
+    pub right: Option<Vec<usize>>,
+}
+
+impl std::fmt::Display for Neighbours {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sides = [
+            ("top", &self.top),
+            ("bottom", &self.bottom),
+            ("left", &self.left),
+            ("right", &self.right),
+        ];
+        for (i, (name, tiles)) in sides.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match tiles.as_deref() {
+                None => write!(f, "{} edge", name)?,
+                Some([index]) => write!(f, "{} {}", name, index)?,
+                Some(tiles) => write!(f, "{} one of {} tiles", name, tiles.len())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum GenerationError {
+    // No tile fits at (x, y) and the backtrack budget ran out
+    Contradiction {
+        x: usize,
+        y: usize,
+        neighbours: Neighbours,
+    },
+    // No map can satisfy the tileset and the constraints, nothing fits at (x, y)
+    Unsatisfiable {
+        x: usize,
+        y: usize,
+        neighbours: Neighbours,
+    },
+    // A constraint covers (x, y) which is not on the map
+    ConstraintOutsideMap { x: usize, y: usize },
+    // A constraint or the surroundings name an atlas index that has no tile in the tileset
+    UnknownTile { index: usize },
+    // The tileset can't be used by the solver
+    InvalidTileset(&'static str),
+    // The sample of the overlapping model can't be learned from
+    InvalidSample(&'static str),
+    // A check still fails at (x, y) after every repair the options allow
+    Unrepaired { x: usize, y: usize },
+}
+
+impl std::fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GenerationError::Contradiction { x, y, neighbours } => write!(
+                f,
+                "no tile fits at ({}, {}) and the backtrack budget ran out, neighbours: {}",
+                x, y, neighbours
+            ),
+            GenerationError::Unsatisfiable { x, y, neighbours } => write!(
+                f,
+                "the constraints can't be satisfied, no tile fits at ({}, {}), neighbours: {}",
+                x, y, neighbours
+            ),
+            GenerationError::ConstraintOutsideMap { x, y } => {
+                write!(f, "constraint covers ({}, {}) which is outside the map", x, y)
+            }
+            GenerationError::UnknownTile { index } => {
+                write!(f, "there is no tile with index {} in the tileset", index)
+            }
+            GenerationError::InvalidTileset(reason) => write!(f, "invalid tileset: {}", reason),
+            GenerationError::InvalidSample(reason) => write!(f, "invalid sample: {}", reason),
+            GenerationError::Unrepaired { x, y } => write!(
+                f,
+                "the map still breaks a check at ({}, {}) after every repair",
+                x, y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+// A map from a random seed, returned with the seed so it can be generated again
+pub fn generate_map(
+    tileset: &Tileset,
+    width: usize,
+    height: usize,
+) -> Result<(Map, u64), GenerationError> {
+    let seed = rand::random();
+    let map = generate_map_with_seed(tileset, width, height, seed)?;
+    Ok((map, seed))
+}
+
+// Same seed always yields the same tile grid
+pub fn generate_map_with_seed(
+    tileset: &Tileset,
+    width: usize,
+    height: usize,
+    seed: u64,
+) -> Result<Map, GenerationError> {
+    generate_map_with_options(tileset, &GenerationOptions {
+        width,
+        height,
+        seed,
+        ..Default::default()
+    })
+}
+
+pub fn generate_map_with_options(
+    tileset: &Tileset,
+    options: &GenerationOptions,
+) -> Result<Map, GenerationError> {
+    let map = Generator::new(tileset, options)?.run()?;
+    repair(tileset, options, map)
+}
+
+// Cells around a violation generated again, so what replaces it can join the rest of the map
+const REPAIR_MARGIN: usize = 1;
+
+// Generates the cells around each violation of the checks again, the rest of the map stays
+fn repair(
+    tileset: &Tileset,
+    options: &GenerationOptions,
+    mut map: Map,
+) -> Result<Map, GenerationError> {
+    let mut repairs = 0;
+    loop {
+        let Some(cells) = options
+            .checks
+            .iter()
+            .find_map(|check| check.violation(tileset, &map))
+            .filter(|cells| !cells.is_empty())
+        else {
+            return Ok(map);
+        };
+        if repairs == options.max_repairs {
+            let (x, y) = cells[0];
+            return Err(GenerationError::Unrepaired { x, y });
+        }
+        repairs += 1;
+        options.progress.set_repairs(repairs);
+
+        let (width, height) = (map.width(), map.height());
+        let xs = cells.iter().map(|(x, _)| *x);
+        let ys = cells.iter().map(|(_, y)| *y);
+        let left = xs.clone().min().unwrap().saturating_sub(REPAIR_MARGIN);
+        let bottom = ys.clone().min().unwrap().saturating_sub(REPAIR_MARGIN);
+        let right = (xs.max().unwrap() + REPAIR_MARGIN + 1).min(width);
+        let top = (ys.max().unwrap() + REPAIR_MARGIN + 1).min(height);
+
+        // the map around the region is its surroundings, where there is none it keeps the
+        // border and surroundings of the whole map
+        let row = |y: usize| {
+            (left..right)
+                .map(|x| (map[(x, y)], map.orientation(x, y)))
+                .collect()
+        };
+        let column = |x: usize| {
+            (bottom..top)
+                .map(|y| (map[(x, y)], map.orientation(x, y)))
+                .collect()
+        };
+        let outside = |side: &[(usize, Orientation)], start: usize, end: usize| {
+            side.iter().skip(start).take(end - start).copied().collect()
+        };
+        let surroundings = Surroundings {
+            top: if top < height {
+                row(top)
+            } else {
+                outside(&options.surroundings.top, left, right)
+            },
+            bottom: if bottom > 0 {
+                row(bottom - 1)
+            } else {
+                outside(&options.surroundings.bottom, left, right)
+            },
+            left: if left > 0 {
+                column(left - 1)
+            } else {
+                outside(&options.surroundings.left, bottom, top)
+            },
+            right: if right < width {
+                column(right)
+            } else {
+                outside(&options.surroundings.right, bottom, top)
+            },
+        };
+        let border = Border {
+            top: options.border.top.filter(|_| top == height),
+            bottom: options.border.bottom.filter(|_| bottom == 0),
+            left: options.border.left.filter(|_| left == 0),
+            right: options.border.right.filter(|_| right == width),
+        };
+        let constraints = options
+            .constraints
+            .iter()
+            .flat_map(|constraint| {
+                constraint
+                    .region
+                    .cells(width, height)
+                    .into_iter()
+                    .filter(|&(x, y)| (left..right).contains(&x) && (bottom..top).contains(&y))
+                    .map(|(x, y)| Constraint {
+                        region: Region::Cell {
+                            x: x - left,
+                            y: y - bottom,
+                        },
+                        allowed: constraint.allowed.clone(),
+                    })
+            })
+            .collect();
+        let region_options = GenerationOptions {
+            width: right - left,
+            height: top - bottom,
+            seed: options.seed.wrapping_add(repairs as u64),
+            max_backtracks: options.max_backtracks,
+            heuristic: options.heuristic.clone(),
+            constraints,
+            border,
+            surroundings,
+            model: options.model.clone(),
+            ..Default::default()
+        };
+
+        // a region that can't be generated is tried again with the next seed
+        let Ok(region) = Generator::new(tileset, &region_options)?.run() else {
+            continue;
+        };
+        for (x, y, index) in region.iter() {
+            let cell = map.cell(left + x, bottom + y);
+            map.tiles[cell] = index;
+            map.orientations[cell] = region.orientation(x, y);
+        }
+    }
+}
+
+// What the last call to Generator::step did
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    // The border and the constraints have been applied and propagated
+    Started,
+    // A cell was collapsed and the change propagated, backtracking first if needed
+    Collapsed { x: usize, y: usize },
+    // Every cell is collapsed, the map is ready
+    Done,
+}
+
+enum State {
+    Start,
+    Running,
+    Done,
+    Failed(GenerationError),
+}
+
+// Runs the solver one collapse at a time, so callers can watch the map being built
+pub struct Generator {
+    solver: Solver,
+    state: State,
+}
+
+impl Generator {
+    pub fn new(
+        tileset: &Tileset,
+        options: &GenerationOptions,
+    ) -> Result<Generator, GenerationError> {
+        Ok(Generator {
+            solver: Solver::new(Rules::new(tileset, &options.model)?, options),
+            state: State::Start,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.solver.wave.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.solver.wave.height
+    }
+
+    // Number of different tiles a cell can start with
+    pub fn tile_count(&self) -> usize {
+        self.solver.rules.len()
+    }
+
+    // Does one more step of the generation, once done or failed it keeps returning the same
+    pub fn step(&mut self) -> Result<Step, GenerationError> {
+        let result = match &self.state {
+            State::Start => self.solver.start().map(|_| Step::Started),
+            State::Running => match self.solver.lowest_entropy_cell() {
+                Some(cell) => self.solver.decide(cell).map(|_| {
+                    let (x, y) = self.solver.wave.coordinates(cell);
+                    Step::Collapsed { x, y }
+                }),
+                None => Ok(Step::Done),
+            },
+            State::Done => Ok(Step::Done),
+            State::Failed(err) => Err(err.clone()),
+        };
+        self.state = match &result {
+            Ok(Step::Done) => State::Done,
+            Ok(_) => State::Running,
+            Err(err) => State::Failed(err.clone()),
+        };
+        result
+    }
+
+    // Steps until the map is done
+    pub fn run(mut self) -> Result<Map, GenerationError> {
+        while self.step()? != Step::Done {}
+        Ok(self.solver.map())
+    }
+
+    // The finished map, None until step returns Done
+    pub fn map(&self) -> Option<Map> {
+        match self.state {
+            State::Done => Some(self.solver.map()),
+            _ => None,
+        }
+    }
+
+    // Atlas index and orientation of the tile at (x, y), if only one is left
+    pub fn tile(&self, x: usize, y: usize) -> Option<(usize, Orientation)> {
+        let rules = &self.solver.rules;
+        let wave = &self.solver.wave;
+        let cell = y * wave.width + x;
+        if wave.counts[cell] == 1 {
+            wave.tiles(cell)
+                .next()
+                .map(|tile| (rules.atlas_indexes[tile], rules.orientations[tile]))
+        } else {
+            None
+        }
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> CellEntropy {
+        let wave = &self.solver.wave;
+        wave.entropy(y * wave.width + x)
+    }
+}
+
+// What the solver places in the cells, numbered 0..len. The variants of the tileset in atlas
+// index then orientation order, or the patterns of the overlapping model each standing for the
+// variant in its corner.
+struct Rules {
+    atlas_indexes: Vec<usize>,
+    orientations: Vec<Orientation>,
+    kinds: Vec<TileKind>,
+    // edges[direction][tile]
+    edges: [Vec<(TileKind, TileKind)>; 4],
+    weights: Vec<f32>,
+    // weight * ln(weight) of each tile, for the entropy
+    weight_log_weights: Vec<f64>,
+    // compatible[direction][tile] lists the tiles that may sit in that direction of tile
+    compatible: [Vec<Vec<usize>>; 4],
+}
+
+impl Rules {
+    fn new(tileset: &Tileset, model: &Model) -> Result<Rules, GenerationError> {
+        match model {
+            Model::SimpleTiled => Rules::simple_tiled(tileset),
+            Model::Overlapping {
+                sample,
+                size,
+                periodic,
+            } => Rules::overlapping(tileset, sample, *size, *periodic),
+        }
+    }
+
+    fn simple_tiled(tileset: &Tileset) -> Result<Rules, GenerationError> {
+        let tiles = tileset
+            .tiles()
+            .flat_map(|tile| {
+                tile.orientations()
+                    .into_iter()
+                    .map(|orientation| (tile.oriented(orientation), orientation))
+            })
+            .collect::<Vec<_>>();
+        let (tiles, orientations): (Vec<Tile>, Vec<Orientation>) = tiles.into_iter().unzip();
+
+        let variants = tiles
+            .iter()
+            .zip(orientations.iter().copied())
+            .collect::<Vec<_>>();
+        let compatible = DIRECTIONS.map(|direction| {
+            variants
+                .iter()
+                .map(|&variant| {
+                    (0..variants.len())
+                        .filter(|&other| direction.fits(variant, variants[other]))
+                        .collect()
+                })
+                .collect()
+        });
+
+        let weights = tiles.iter().map(|tile| tile.weight).collect();
+        Rules::build(tiles, orientations, weights, compatible)
+    }
+
+    fn overlapping(
+        tileset: &Tileset,
+        sample: &Map,
+        size: usize,
+        periodic: bool,
+    ) -> Result<Rules, GenerationError> {
+        if size == 0 {
+            return Err(GenerationError::InvalidSample(
+                "patterns need at least one cell",
+            ));
+        }
+        if sample.width() < size || sample.height() < size {
+            return Err(GenerationError::InvalidSample(
+                "it is smaller than the patterns",
+            ));
+        }
+
+        // how often each pattern appears, cells row by row from the bottom. Sorted so the same
+        // sample always gives the same numbering.
+        let (width, height) = (sample.width(), sample.height());
+        let (last_x, last_y) = match periodic {
+            true => (width - 1, height - 1),
+            false => (width - size, height - size),
+        };
+        let mut counts = BTreeMap::<Vec<(usize, Orientation)>, usize>::new();
+        for y in 0..=last_y {
+            for x in 0..=last_x {
+                let pattern = (0..size)
+                    .flat_map(|dy| (0..size).map(move |dx| ((x + dx) % width, (y + dy) % height)))
+                    .map(|(x, y)| (sample[(x, y)], sample.orientation(x, y)))
+                    .collect();
+                *counts.entry(pattern).or_default() += 1;
+            }
+        }
+        let patterns = counts.keys().collect::<Vec<_>>();
+
+        // a pattern fits next to another when they agree on the cells they share once it's
+        // shifted one cell that way
+        let agree =
+            |pattern: &[(usize, Orientation)], other: &[(usize, Orientation)], direction| {
+                let (dx, dy) = match direction {
+                    Top => (0, 1),
+                    Bottom => (0, -1),
+                    Left => (-1, 0),
+                    Right => (1, 0),
+                };
+                let size = size as isize;
+                (0..size).all(|y| {
+                    (0..size).all(|x| {
+                        let (ox, oy) = (x - dx, y - dy);
+                        !(0..size).contains(&ox)
+                            || !(0..size).contains(&oy)
+                            || pattern[(y * size + x) as usize] == other[(oy * size + ox) as usize]
+                    })
+                })
+            };
+        let compatible = DIRECTIONS.map(|direction| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    (0..patterns.len())
+                        .filter(|&other| agree(pattern, patterns[other], direction))
+                        .collect()
+                })
+                .collect()
+        });
+
+        let (tiles, orientations) = patterns
+            .iter()
+            .map(|pattern| {
+                let (index, orientation) = pattern[0];
+                match tileset.get(index) {
+                    Some(tile) => Ok((tile.oriented(orientation), orientation)),
+                    None => Err(GenerationError::UnknownTile { index }),
+                }
+            })
+            .collect::<Result<(Vec<Tile>, Vec<Orientation>), _>>()?;
+        let weights = counts.values().map(|count| *count as f32).collect();
+        Rules::build(tiles, orientations, weights, compatible)
+    }
+
+    fn build(
+        tiles: Vec<Tile>,
+        orientations: Vec<Orientation>,
+        weights: Vec<f32>,
+        compatible: [Vec<Vec<usize>>; 4],
+    ) -> Result<Rules, GenerationError> {
+        if tiles.is_empty() {
+            return Err(GenerationError::InvalidTileset("it has no tiles"));
+        }
+        if tiles.len() >= u16::MAX as usize {
+            return Err(GenerationError::InvalidTileset(
+                "it has too many tiles for the support counters",
+            ));
+        }
+
+        Ok(Rules {
+            atlas_indexes: tiles.iter().map(|tile| tile.index).collect(),
+            orientations,
+            kinds: tiles.iter().map(|tile| tile.kind).collect(),
+            edges: DIRECTIONS.map(|direction| tiles.iter().map(|tile| direction.edge(tile)).collect()),
+            weight_log_weights: weights
+                .iter()
+                .map(|weight| *weight as f64)
+                .map(|weight| if weight > 0.0 { weight * weight.ln() } else { 0.0 })
+                .collect(),
+            weights,
+            compatible,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.atlas_indexes.len()
+    }
+
+    // Tiles numbered by the solver that stand for a variant
+    fn tiles(&self, index: usize, orientation: Orientation) -> Result<Vec<usize>, GenerationError> {
+        let tiles = (0..self.len())
+            .filter(|&tile| {
+                self.atlas_indexes[tile] == index && self.orientations[tile] == orientation
+            })
+            .collect::<Vec<_>>();
+        if tiles.is_empty() {
+            return Err(GenerationError::UnknownTile { index });
+        }
+        Ok(tiles)
+    }
+
+    fn allows(&self, allowed: &Allowed, tile: usize) -> bool {
+        match allowed {
+            Allowed::Kind(kind) => self.kinds[tile] == *kind,
+            Allowed::Tiles(indexes) => indexes.contains(&self.atlas_indexes[tile]),
+        }
+    }
+}
+
+// Domain of every cell as a bitset over the tiles, plus the AC-4 support counters
+struct Wave {
+    width: usize,
+    height: usize,
+    tiles: usize,
+    // u64 words per cell domain
+    words: usize,
+    domains: Vec<u64>,
+    // Tiles left in each cell and the sums the entropy is computed from
+    counts: Vec<usize>,
+    // Cells with a single tile left
+    collapsed: usize,
+    weight_sums: Vec<f64>,
+    weight_log_weight_sums: Vec<f64>,
+    // supports[(cell * tiles + tile) * 4 + direction] counts the tiles of the neighbour in that
+    // direction that still allow tile in cell. Unused towards the map edge.
+    supports: Vec<u16>,
+}
+
+impl Wave {
+    fn new(rules: &Rules, width: usize, height: usize) -> Wave {
+        let tiles = rules.len();
+        let words = tiles.div_ceil(64);
+        let cells = width * height;
+
+        let mut full = vec![u64::MAX; words];
+        if !tiles.is_multiple_of(64) {
+            full[words - 1] = (1 << (tiles % 64)) - 1;
+        }
+
+        // every tile starts supported by all the neighbour tiles compatible with it
+        let mut cell_supports = vec![0; tiles * 4];
+        for tile in 0..tiles {
+            for direction in DIRECTIONS {
+                cell_supports[tile * 4 + direction as usize] =
+                    rules.compatible[direction as usize][tile].len() as u16;
+            }
+        }
+
+        let weight_sum = rules.weights.iter().map(|weight| *weight as f64).sum();
+        let weight_log_weight_sum = rules.weight_log_weights.iter().sum();
+
+        Wave {
+            width,
+            height,
+            tiles,
+            words,
+            domains: full.repeat(cells),
+            counts: vec![tiles; cells],
+            collapsed: if tiles == 1 { cells } else { 0 },
+            weight_sums: vec![weight_sum; cells],
+            weight_log_weight_sums: vec![weight_log_weight_sum; cells],
+            supports: cell_supports.repeat(cells),
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn contains(&self, cell: usize, tile: usize) -> bool {
+        self.domains[cell * self.words + tile / 64] & (1 << (tile % 64)) != 0
+    }
+
+    fn insert(&mut self, rules: &Rules, cell: usize, tile: usize) {
+        self.domains[cell * self.words + tile / 64] |= 1 << (tile % 64);
+        self.counts[cell] += 1;
+        match self.counts[cell] {
+            1 => self.collapsed += 1,
+            2 => self.collapsed -= 1,
+            _ => {}
+        }
+        self.weight_sums[cell] += rules.weights[tile] as f64;
+        self.weight_log_weight_sums[cell] += rules.weight_log_weights[tile];
+    }
+
+    fn remove(&mut self, rules: &Rules, cell: usize, tile: usize) {
+        self.domains[cell * self.words + tile / 64] &= !(1 << (tile % 64));
+        self.counts[cell] -= 1;
+        match self.counts[cell] {
+            1 => self.collapsed += 1,
+            0 => self.collapsed -= 1,
+            _ => {}
+        }
+        self.weight_sums[cell] -= rules.weights[tile] as f64;
+        self.weight_log_weight_sums[cell] -= rules.weight_log_weights[tile];
+    }
+
+    fn entropy(&self, cell: usize) -> CellEntropy {
+        let (x, y) = self.coordinates(cell);
+        CellEntropy {
+            x,
+            y,
+            index: cell,
+            count: self.counts[cell],
+            weight_sum: self.weight_sums[cell],
+            weight_log_weight_sum: self.weight_log_weight_sums[cell],
+        }
+    }
+
+    fn tiles(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        let domain = &self.domains[cell * self.words..(cell + 1) * self.words];
+        domain.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 64 + bit)
+        })
+    }
+
+    fn support(&mut self, cell: usize, tile: usize, direction: Direction) -> &mut u16 {
+        &mut self.supports[(cell * self.tiles + tile) * 4 + direction as usize]
+    }
+
+    fn neighbour(&self, cell: usize, direction: Direction) -> Option<usize> {
+        let (x, y) = self.coordinates(cell);
+        match direction {
+            Top => (y < self.height - 1).then(|| cell + self.width),
+            Bottom => (y > 0).then(|| cell - self.width),
+            Left => (x > 0).then(|| cell - 1),
+            Right => (x < self.width - 1).then(|| cell + 1),
+        }
+    }
+
+    fn coordinates(&self, cell: usize) -> (usize, usize) {
+        (cell % self.width, cell / self.width)
+    }
+}
+
+// Cell waiting in the solver heap, ordered so the lowest entropy pops first
+struct Candidate {
+    entropy: f64,
+    cell: usize,
+    // Version of the cell when queued, older entries are skipped
+    version: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .entropy
+            .total_cmp(&self.entropy)
+            .then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+// A collapse made by the solver and what it needs to undo it
+struct Decision {
+    cell: usize,
+    tile: usize,
+    // Length of the trail before the collapse
+    trail_len: usize,
+}
+
+struct Solver {
+    rules: Rules,
+    heuristic: Arc<dyn Heuristic>,
+    constraints: Vec<Constraint>,
+    border: Border,
+    surroundings: Surroundings,
+    progress: Progress,
+    wave: Wave,
+    rng: StdRng,
+    // Every removal made so far, in order, so backtracking can put them back
+    trail: Vec<(usize, usize)>,
+    // Tiles whose support dropped to zero and still have to be removed
+    pending: Vec<(usize, usize)>,
+    decisions: Vec<Decision>,
+    backtracks: usize,
+    max_backtracks: usize,
+    // Entries go stale when the cell changes after being queued
+    heap: BinaryHeap<Candidate>,
+    versions: Vec<u32>,
+    // Cells changed since they were last queued
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>,
+}
+
+impl Solver {
+    fn new(rules: Rules, options: &GenerationOptions) -> Solver {
+        let wave = Wave::new(&rules, options.width, options.height);
+        let cells = wave.cells();
+
+        Solver {
+            rules,
+            heuristic: options.heuristic.clone(),
+            constraints: options.constraints.clone(),
+            border: options.border,
+            surroundings: options.surroundings.clone(),
+            progress: options.progress.clone(),
+            wave,
+            rng: StdRng::seed_from_u64(options.seed),
+            trail: vec![],
+            pending: vec![],
+            decisions: vec![],
+            backtracks: 0,
+            max_backtracks: options.max_backtracks,
+            heap: BinaryHeap::new(),
+            versions: vec![0; cells],
+            dirty: (0..cells).collect(),
+            is_dirty: vec![true; cells],
+        }
+    }
+
+    // Applies everything known before the first collapse
+    fn start(&mut self) -> Result<(), GenerationError> {
+        // tiles that can't have any neighbour in some direction are out before we start
+        for cell in 0..self.wave.cells() {
+            for direction in DIRECTIONS {
+                if self.wave.neighbour(cell, direction).is_none() {
+                    continue;
+                }
+                for tile in 0..self.rules.len() {
+                    if *self.wave.support(cell, tile, direction) == 0 {
+                        self.pending.push((cell, tile));
+                    }
+                }
+            }
+        }
+        // the edge of the map behaves like a neighbour made only of the border kind
+        for cell in 0..self.wave.cells() {
+            for direction in DIRECTIONS {
+                if self.wave.neighbour(cell, direction).is_some() {
+                    continue;
+                }
+                let Some(kind) = self.border.side(direction) else {
+                    continue;
+                };
+                let banned = self
+                    .wave
+                    .tiles(cell)
+                    .filter(|tile| self.rules.edges[direction as usize][*tile] != (kind, kind))
+                    .collect::<Vec<_>>();
+                for tile in banned {
+                    self.remove(cell, tile)
+                        .map_err(|cell| self.unsatisfiable(cell))?;
+                }
+            }
+        }
+        // tiles already placed beyond the map
+        for cell in 0..self.wave.cells() {
+            for direction in DIRECTIONS {
+                if self.wave.neighbour(cell, direction).is_some() {
+                    continue;
+                }
+                let (x, y) = self.wave.coordinates(cell);
+                let along = match direction {
+                    Top | Bottom => x,
+                    Left | Right => y,
+                };
+                let Some(&(index, orientation)) = self.surroundings.side(direction).get(along)
+                else {
+                    continue;
+                };
+                // any of the patterns for the variant may be the one outside
+                let outside = self.rules.tiles(index, orientation)?;
+                let banned = self
+                    .wave
+                    .tiles(cell)
+                    .filter(|tile| {
+                        !outside.iter().any(|&outside| {
+                            self.rules.compatible[direction.opposite() as usize][outside]
+                                .contains(tile)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                for tile in banned {
+                    self.remove(cell, tile)
+                        .map_err(|cell| self.unsatisfiable(cell))?;
+                }
+            }
+        }
+        for constraint in std::mem::take(&mut self.constraints) {
+            self.restrict(&constraint)?;
+        }
+        if let Err(cell) = self.propagate() {
+            return Err(self.unsatisfiable(cell));
+        }
+        self.queue_dirty();
+        self.progress.set(self.wave.collapsed, self.wave.cells());
+        Ok(())
+    }
+
+    // Collapses the cell and propagates, backtracking until the wave is consistent again
+    fn decide(&mut self, cell: usize) -> Result<(), GenerationError> {
+        let tile = self.choose(cell);
+        self.decisions.push(Decision {
+            cell,
+            tile,
+            trail_len: self.trail.len(),
+        });
+        let mut result = self.collapse(cell, tile);
+
+        while let Err(failed) = result {
+            self.backtracks += 1;
+            let Some(decision) = self.decisions.pop() else {
+                // every choice has been tried
+                return Err(self.unsatisfiable(failed));
+            };
+            if self.backtracks > self.max_backtracks {
+                return Err(self.contradiction(failed));
+            }
+
+            // ban the choice that failed, the ban itself belongs to the previous decision
+            self.undo(decision.trail_len);
+            result = self
+                .remove(decision.cell, decision.tile)
+                .and_then(|_| self.propagate());
+        }
+
+        self.queue_dirty();
+        self.progress.set(self.wave.collapsed, self.wave.cells());
+        Ok(())
+    }
+
+    // Variants of the collapsed wave
+    fn map(&self) -> Map {
+        let wave = &self.wave;
+        let tiles = (0..wave.cells())
+            .map(|cell| match wave.tiles(cell).next() {
+                Some(tile) => tile,
+                None => unreachable!("a finished wave has a tile in every cell"),
+            })
+            .collect::<Vec<_>>();
+        Map {
+            width: wave.width,
+            height: wave.height,
+            tiles: tiles
+                .iter()
+                .map(|&tile| self.rules.atlas_indexes[tile])
+                .collect(),
+            orientations: tiles
+                .iter()
+                .map(|&tile| self.rules.orientations[tile])
+                .collect(),
+        }
+    }
+
+    // Removes the tiles a constraint doesn't allow, propagation is left to the caller
+    fn restrict(&mut self, constraint: &Constraint) -> Result<(), GenerationError> {
+        if let Allowed::Tiles(indexes) = &constraint.allowed {
+            for &index in indexes {
+                if !self.rules.atlas_indexes.contains(&index) {
+                    return Err(GenerationError::UnknownTile { index });
+                }
+            }
+        }
+        let (width, height) = (self.wave.width, self.wave.height);
+        for (x, y) in constraint.region.cells(width, height) {
+            if x >= width || y >= height {
+                return Err(GenerationError::ConstraintOutsideMap { x, y });
+            }
+            let cell = y * width + x;
+            let banned = self
+                .wave
+                .tiles(cell)
+                .filter(|tile| !self.rules.allows(&constraint.allowed, *tile))
+                .collect::<Vec<_>>();
+            for tile in banned {
+                self.remove(cell, tile)
+                    .map_err(|cell| self.unsatisfiable(cell))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn contradiction(&self, cell: usize) -> GenerationError {
+        let (x, y) = self.wave.coordinates(cell);
+        GenerationError::Contradiction {
+            x,
+            y,
+            neighbours: self.neighbours(cell),
+        }
+    }
+
+    fn unsatisfiable(&self, cell: usize) -> GenerationError {
+        let (x, y) = self.wave.coordinates(cell);
+        GenerationError::Unsatisfiable {
+            x,
+            y,
+            neighbours: self.neighbours(cell),
+        }
+    }
+
+    // What the neighbours of the cell can still become, taken before anything is undone
+    fn neighbours(&self, cell: usize) -> Neighbours {
+        let side = |direction| {
+            self.wave.neighbour(cell, direction).map(|neighbour| {
+                self.wave
+                    .tiles(neighbour)
+                    .map(|tile| self.rules.atlas_indexes[tile])
+                    .collect()
+            })
+        };
+        Neighbours {
+            top: side(Top),
+            bottom: side(Bottom),
+            left: side(Left),
+            right: side(Right),
+        }
+    }
+
+    fn lowest_entropy_cell(&mut self) -> Option<usize> {
+        while let Some(Candidate { cell, version, .. }) = self.heap.pop() {
+            if version == self.versions[cell] && self.wave.counts[cell] > 1 {
+                return Some(cell);
+            }
+        }
+        None
+    }
+
+    fn queue_dirty(&mut self) {
+        for cell in std::mem::take(&mut self.dirty) {
+            self.is_dirty[cell] = false;
+            self.versions[cell] = self.versions[cell].wrapping_add(1);
+            if self.wave.counts[cell] > 1 {
+                // small random noise so ties don't always go to the same cell
+                let noise = self.rng.random::<f64>() * 1e-6;
+                self.heap.push(Candidate {
+                    entropy: self.heuristic.entropy(&self.wave.entropy(cell)) + noise,
+                    cell,
+                    version: self.versions[cell],
+                });
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, cell: usize) {
+        if !self.is_dirty[cell] {
+            self.is_dirty[cell] = true;
+            self.dirty.push(cell);
+        }
+    }
+
+    // Picks one of the cell's tiles at random according to their weights
+    fn choose(&mut self, cell: usize) -> usize {
+        let tiles = self.wave.tiles(cell).collect::<Vec<_>>();
+        let probabilities = tiles
+            .iter()
+            .map(|tile| self.rules.weights[*tile])
+            .collect::<Vec<f32>>();
+
+        let sum: f32 = probabilities.iter().sum();
+
+        // only zero weight tiles left, pick any of them
+        if sum <= 0.0 {
+            return tiles[self.rng.random_range(0..tiles.len())];
+        }
+
+        let mut random = self.rng.random_range(0.0..sum);
+
+        for (i, probability) in probabilities.iter().enumerate() {
+            random -= probability;
+            if random <= 0.0 {
+                return tiles[i];
+            }
+        }
+
+        // float rounding left a tiny remainder, settle on the last tile that has weight
+        let last = probabilities
+            .iter()
+            .rposition(|probability| *probability > 0.0)
+            .unwrap_or(tiles.len() - 1);
+        tiles[last]
+    }
+
+    fn collapse(&mut self, cell: usize, tile: usize) -> Result<(), usize> {
+        let others = self.wave.tiles(cell).filter(|other| *other != tile).collect::<Vec<_>>();
+        for other in others {
+            self.remove(cell, other)?;
+        }
+        self.propagate()
+    }
+
+    // Removes a tile from a cell and takes its support away from the neighbours.
+    // Fails with the cell if it has no tiles left.
+    fn remove(&mut self, cell: usize, tile: usize) -> Result<(), usize> {
+        self.wave.remove(&self.rules, cell, tile);
+        self.trail.push((cell, tile));
+        self.mark_dirty(cell);
+
+        for direction in DIRECTIONS {
+            let Some(neighbour) = self.wave.neighbour(cell, direction) else {
+                continue;
+            };
+            for &other in &self.rules.compatible[direction as usize][tile] {
+                let support = self.wave.support(neighbour, other, direction.opposite());
+                *support -= 1;
+                if *support == 0 {
+                    self.pending.push((neighbour, other));
+                }
+            }
+        }
+
+        if self.wave.counts[cell] == 0 {
+            Err(cell)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Removes every tile left without support until nothing changes
+    fn propagate(&mut self) -> Result<(), usize> {
+        while let Some((cell, tile)) = self.pending.pop() {
+            if self.wave.contains(cell, tile) {
+                self.remove(cell, tile)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Puts back every removal made after the trail had the given length
+    fn undo(&mut self, trail_len: usize) {
+        self.pending.clear();
+
+        while self.trail.len() > trail_len {
+            let (cell, tile) = self.trail.pop().unwrap();
+            self.wave.insert(&self.rules, cell, tile);
+            self.mark_dirty(cell);
+
+            for direction in DIRECTIONS {
+                let Some(neighbour) = self.wave.neighbour(cell, direction) else {
+                    continue;
+                };
+                for &other in &self.rules.compatible[direction as usize][tile] {
+                    *self.wave.support(neighbour, other, direction.opposite()) += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::tileset::{load_tileset, plain_tile, test_tileset};
+
+    type Variant = (usize, Orientation);
+
+    // Every pair of neighbouring cells, as atlas index and orientation, and the direction from
+    // the first to the second
+    fn adjacencies(map: &Map) -> BTreeSet<(Variant, usize, Variant)> {
+        let mut pairs = BTreeSet::new();
+        for (x, y, index) in map.iter() {
+            for direction in DIRECTIONS {
+                if let Some((other_x, other_y)) = map.neighbour(x, y, direction) {
+                    pairs.insert((
+                        (index, map.orientation(x, y)),
+                        direction as usize,
+                        (map[(other_x, other_y)], map.orientation(other_x, other_y)),
+                    ));
+                }
+            }
+        }
+        pairs
+    }
+
+    // Whether every cell fits its neighbours
+    fn assert_fits(tileset: &Tileset, map: &Map) {
+        let variant = |x, y| {
+            let orientation = map.orientation(x, y);
+            let tile = tileset.get(map[(x, y)]).unwrap();
+            (tile.oriented(orientation), orientation)
+        };
+        for (x, y, _) in map.iter() {
+            let (tile, orientation) = variant(x, y);
+            for direction in DIRECTIONS {
+                let Some((other_x, other_y)) = map.neighbour(x, y, direction) else {
+                    continue;
+                };
+                let (other, other_orientation) = variant(other_x, other_y);
+                assert!(
+                    direction.fits((&tile, orientation), (&other, other_orientation)),
+                    "({}, {}) doesn't fit its {} neighbour",
+                    x,
+                    y,
+                    direction
+                );
+            }
+        }
+    }
+
+    // Three plain tiles that may not be next to their own kind, so the map is coloured like a
+    // grid with three colours. Propagation only catches a mistake once a cell is down to one
+    // colour, which makes the solver back out of choices.
+    fn colours() -> Tileset {
+        let tiles = ["Grass", "Water", "Forest"]
+            .iter()
+            .enumerate()
+            .map(|(index, kind)| {
+                let deny = format!(
+                    ", deny: (top: [Kind({0})], bottom: [Kind({0})], left: [Kind({0})], \
+                     right: [Kind({0})])",
+                    kind
+                );
+                plain_tile(index, kind, &deny)
+            })
+            .collect::<Vec<_>>();
+        test_tileset(&tiles)
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_map() {
+        let tileset = load_tileset();
+        let map = generate_map_with_seed(&tileset, 20, 20, 7).unwrap();
+        assert_eq!(generate_map_with_seed(&tileset, 20, 20, 7).unwrap(), map);
+        assert_ne!(generate_map_with_seed(&tileset, 20, 20, 8).unwrap(), map);
+    }
+
+    #[test]
+    fn steps_through_the_generation() {
+        let tileset = load_tileset();
+        let options = GenerationOptions {
+            width: 10,
+            height: 10,
+            seed: 5,
+            ..Default::default()
+        };
+        let mut generator = Generator::new(&tileset, &options).unwrap();
+        assert_eq!(generator.step().unwrap(), Step::Started);
+        assert!(generator.map().is_none());
+
+        let mut collapsed = 0;
+        loop {
+            match generator.step().unwrap() {
+                Step::Collapsed { x, y } => {
+                    assert!(x < 10 && y < 10);
+                    assert!(generator.tile(x, y).is_some());
+                    assert!(generator.map().is_none());
+                    collapsed += 1;
+                }
+                Step::Done => break,
+                Step::Started => panic!("started twice"),
+            }
+        }
+        assert!(collapsed > 0);
+        // stays done, with the map generate_map_with_seed makes
+        assert_eq!(generator.step().unwrap(), Step::Done);
+        assert_eq!(
+            generator.map(),
+            Some(generate_map_with_seed(&tileset, 10, 10, 5).unwrap())
+        );
+    }
+
+    #[test]
+    fn generates_only_fitting_neighbours() {
+        let tileset = load_tileset();
+        for seed in 0..5 {
+            let map = generate_map_with_seed(&tileset, 20, 20, seed).unwrap();
+            assert_fits(&tileset, &map);
+        }
+    }
+
+    #[test]
+    fn backtracks_out_of_contradictions() {
+        let tileset = colours();
+        let options = GenerationOptions {
+            width: 20,
+            height: 20,
+            seed: 0,
+            max_backtracks: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            generate_map_with_options(&tileset, &options),
+            Err(GenerationError::Contradiction { .. })
+        ));
+
+        let options = GenerationOptions {
+            max_backtracks: 1000,
+            ..options
+        };
+        let map = generate_map_with_options(&tileset, &options).unwrap();
+        assert_fits(&tileset, &map);
+    }
+
+    #[test]
+    fn reports_unsatisfiable_tilesets() {
+        // grass that can't have grass on its right, and nothing else
+        let tileset = test_tileset(&[plain_tile(0, "Grass", ", deny: (right: [Kind(Grass)])")]);
+        assert!(matches!(
+            generate_map_with_seed(&tileset, 2, 1, 0),
+            Err(GenerationError::Unsatisfiable { .. })
+        ));
+    }
+
+    // A cell at (1, 2) of a 10 wide map that can still become tiles of these weights
+    fn cell_entropy(weights: &[f64]) -> CellEntropy {
+        CellEntropy {
+            x: 1,
+            y: 2,
+            index: 21,
+            count: weights.len(),
+            weight_sum: weights.iter().sum(),
+            weight_log_weight_sum: weights
+                .iter()
+                .map(|weight| if *weight > 0.0 { weight * weight.ln() } else { 0.0 })
+                .sum(),
+        }
+    }
+
+    #[test]
+    fn measures_entropy() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // equal weights, however big, leave any of the n tiles equally likely
+        assert!(close(Shannon.entropy(&cell_entropy(&[2.0; 4])), 4f64.ln()));
+        assert!(close(Shannon.entropy(&cell_entropy(&[0.5; 3])), 3f64.ln()));
+        assert!(close(Shannon.entropy(&cell_entropy(&[0.0; 3])), 3f64.ln()));
+        // one likely tile is less uncertain than two equal ones
+        let expected = -(0.25 * 0.25f64.ln() + 0.75 * 0.75f64.ln());
+        assert!(close(Shannon.entropy(&cell_entropy(&[1.0, 3.0])), expected));
+        assert!(close(Shannon.entropy(&cell_entropy(&[5.0])), 0.0));
+
+        assert_eq!(MinCount.entropy(&cell_entropy(&[1.0, 3.0, 9.0])), 3.0);
+        assert_eq!(Scanline.entropy(&cell_entropy(&[1.0, 3.0])), 21.0);
+    }
+
+    #[test]
+    fn generates_with_every_heuristic() {
+        let tileset = load_tileset();
+        let heuristics: [Arc<dyn Heuristic>; 3] =
+            [Arc::new(Shannon), Arc::new(MinCount), Arc::new(Scanline)];
+        for heuristic in heuristics {
+            let options = GenerationOptions {
+                width: 20,
+                height: 20,
+                seed: 3,
+                heuristic,
+                ..Default::default()
+            };
+            let map = generate_map_with_options(&tileset, &options).unwrap();
+            assert_fits(&tileset, &map);
+        }
+    }
+
+    fn generate_constrained(constraints: Vec<Constraint>) -> Result<Map, GenerationError> {
+        let options = GenerationOptions {
+            width: 20,
+            height: 20,
+            seed: 0,
+            constraints,
+            ..Default::default()
+        };
+        generate_map_with_options(&load_tileset(), &options)
+    }
+
+    #[test]
+    fn pins_cells_and_kinds() {
+        // crossroads have no weight, so only a constraint puts one down
+        let map = generate_constrained(vec![
+            Constraint {
+                region: Region::Cell { x: 5, y: 5 },
+                allowed: Allowed::Tiles(vec![42]),
+            },
+            Constraint {
+                region: Region::Column(0),
+                allowed: Allowed::Kind(TileKind::Water),
+            },
+        ])
+        .unwrap();
+        let tileset = load_tileset();
+        assert_eq!(map[(5, 5)], 42);
+        for y in 0..map.height() {
+            assert_eq!(tileset.get(map[(0, y)]).unwrap().kind, TileKind::Water);
+        }
+        assert_fits(&tileset, &map);
+    }
+
+    #[test]
+    fn rejects_bad_constraints() {
+        let outside = Constraint {
+            region: Region::Cell { x: 20, y: 3 },
+            allowed: Allowed::Kind(TileKind::Grass),
+        };
+        assert!(matches!(
+            generate_constrained(vec![outside]),
+            Err(GenerationError::ConstraintOutsideMap { x: 20, y: 3 })
+        ));
+
+        let unknown = Constraint {
+            region: Region::Row(0),
+            allowed: Allowed::Tiles(vec![70, 99]),
+        };
+        assert!(matches!(
+            generate_constrained(vec![unknown]),
+            Err(GenerationError::UnknownTile { index: 99 })
+        ));
+
+        // open water can't be next to a road
+        let contradictory = vec![
+            Constraint {
+                region: Region::Cell { x: 3, y: 3 },
+                allowed: Allowed::Tiles(vec![14]),
+            },
+            Constraint {
+                region: Region::Cell { x: 4, y: 3 },
+                allowed: Allowed::Kind(TileKind::Road),
+            },
+        ];
+        assert!(matches!(
+            generate_constrained(contradictory),
+            Err(GenerationError::Unsatisfiable { .. })
+        ));
+    }
+
+    #[test]
+    fn surrounds_islands_with_water() {
+        let tileset = load_tileset();
+        for seed in 0..3 {
+            let options = GenerationOptions {
+                width: 20,
+                height: 20,
+                seed,
+                border: Border::uniform(TileKind::Water),
+                ..Default::default()
+            };
+            let map = generate_map_with_options(&tileset, &options).unwrap();
+            for (x, y, index) in map.iter() {
+                let tile = tileset.get(index).unwrap().oriented(map.orientation(x, y));
+                for direction in DIRECTIONS {
+                    if map.neighbour(x, y, direction).is_none() {
+                        assert_eq!(
+                            direction.edge(&tile),
+                            (TileKind::Water, TileKind::Water),
+                            "{} edge of ({}, {})",
+                            direction,
+                            x,
+                            y
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fits_the_maps_around_it() {
+        let tileset = load_tileset();
+        let placed = |map: &Map, x, y| (map[(x, y)], map.orientation(x, y));
+        let first = generate_map_with_seed(&tileset, 10, 10, 1).unwrap();
+
+        // one map to the right of the first, then one above it
+        let right = GenerationOptions {
+            width: 10,
+            height: 10,
+            seed: 2,
+            surroundings: Surroundings {
+                left: (0..10).map(|y| placed(&first, 9, y)).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let right = generate_map_with_options(&tileset, &right).unwrap();
+        let above = GenerationOptions {
+            width: 10,
+            height: 10,
+            seed: 3,
+            surroundings: Surroundings {
+                bottom: (0..10).map(|x| placed(&first, x, 9)).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let above = generate_map_with_options(&tileset, &above).unwrap();
+
+        // the first map with the other one placed at the offset right of it or above it
+        let join = |other: &Map, (dx, dy): (usize, usize)| {
+            let width = 10 + dx;
+            let height = 10 + dy;
+            let cell = |x: usize, y: usize| match (x.checked_sub(dx), y.checked_sub(dy)) {
+                (Some(x), Some(y)) => placed(other, x, y),
+                _ => placed(&first, x, y),
+            };
+            let columns = (0..width)
+                .map(|x| (0..height).map(|y| cell(x, y).0).collect())
+                .collect();
+            let mut joined = Map::from_columns(columns).unwrap();
+            for x in 0..width {
+                for y in 0..height {
+                    joined.set_orientation(x, y, cell(x, y).1);
+                }
+            }
+            joined
+        };
+        assert_fits(&tileset, &join(&right, (10, 0)));
+        assert_fits(&tileset, &join(&above, (0, 10)));
+    }
+
+    #[test]
+    fn overlapping_keeps_the_neighbours_of_the_sample() {
+        let tileset = load_tileset();
+        // an ordinary map, its opposite sides don't fit together
+        let sample = generate_map_with_seed(&tileset, 12, 12, 1).unwrap();
+        let seen = adjacencies(&sample);
+        for (size, width) in [(2, 30), (3, 10)] {
+            let options = GenerationOptions {
+                width,
+                height: width,
+                seed: 1,
+                model: Model::Overlapping {
+                    sample: sample.clone(),
+                    size,
+                    periodic: false,
+                },
+                ..Default::default()
+            };
+            let map = generate_map_with_options(&tileset, &options).unwrap();
+            assert!(
+                adjacencies(&map).is_subset(&seen),
+                "patterns of size {}",
+                size
+            );
+        }
+    }
+}
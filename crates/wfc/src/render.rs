@@ -0,0 +1,281 @@
+use std::path::Path;
+
+use image::{RgbaImage, imageops};
+
+use crate::map::Map;
+use crate::tileset::{Orientation, Tileset};
+
+#[derive(Debug)]
+pub enum RenderError {
+    Image(image::ImageError),
+    // The atlas image is smaller than the grid the tileset describes
+    AtlasTooSmall { width: u32, height: u32 },
+    // The image read as a map isn't a whole number of tiles
+    NotTileGrid { width: u32, height: u32 },
+    // No variant of any tile looks like this cell of the image
+    UnknownCell { x: usize, y: usize },
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RenderError::Image(err) => write!(f, "image error: {}", err),
+            RenderError::AtlasTooSmall { width, height } => write!(
+                f,
+                "atlas is {}x{} pixels, too small for the tileset grid",
+                width, height
+            ),
+            RenderError::NotTileGrid { width, height } => write!(
+                f,
+                "image is {}x{} pixels, which isn't a whole number of tiles",
+                width, height
+            ),
+            RenderError::UnknownCell { x, y } => {
+                write!(f, "cell at ({}, {}) doesn't look like any tile", x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<image::ImageError> for RenderError {
+    fn from(err: image::ImageError) -> Self {
+        RenderError::Image(err)
+    }
+}
+
+// Draws a map by copying the cell of each tile out of the atlas image, turned and mirrored
+// the way its variant is
+pub fn render_map(
+    tileset: &Tileset,
+    atlas: &RgbaImage,
+    map: &Map,
+) -> Result<RgbaImage, RenderError> {
+    check_atlas(tileset, atlas)?;
+
+    let size = tileset.tile_size;
+    let width = map.width() as u32;
+    let height = map.height() as u32;
+    let mut image = RgbaImage::new(width * size, height * size);
+    for (x, y, index) in map.iter() {
+        let cell = cell(tileset, atlas, index, map.orientation(x, y));
+        // image rows go top to bottom, our y goes up
+        let row = height - 1 - y as u32;
+        imageops::replace(
+            &mut image,
+            &cell,
+            (x as u32 * size) as i64,
+            (row * size) as i64,
+        );
+    }
+    Ok(image)
+}
+
+// The other way around, finds the variant drawn in each cell of an image made from the atlas,
+// e.g. a hand drawn sample for the overlapping model
+pub fn read_map(
+    tileset: &Tileset,
+    atlas: &RgbaImage,
+    image: &RgbaImage,
+) -> Result<Map, RenderError> {
+    check_atlas(tileset, atlas)?;
+
+    let size = tileset.tile_size;
+    let (width, height) = (image.width() / size, image.height() / size);
+    if width == 0
+        || height == 0
+        || !image.width().is_multiple_of(size)
+        || !image.height().is_multiple_of(size)
+    {
+        return Err(RenderError::NotTileGrid {
+            width: image.width(),
+            height: image.height(),
+        });
+    }
+    let variants = tileset
+        .tiles()
+        .flat_map(|tile| {
+            tile.orientations().into_iter().map(|orientation| {
+                (
+                    tile.index,
+                    orientation,
+                    cell(tileset, atlas, tile.index, orientation),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut columns = vec![vec![0; height as usize]; width as usize];
+    let mut orientations = vec![];
+    for x in 0..width {
+        for row in 0..height {
+            let (x, y) = (x as usize, (height - 1 - row) as usize);
+            let pixels =
+                imageops::crop_imm(image, x as u32 * size, row * size, size, size).to_image();
+            let Some((index, orientation, _)) =
+                variants.iter().find(|(_, _, cell)| *cell == pixels)
+            else {
+                return Err(RenderError::UnknownCell { x, y });
+            };
+            columns[x][y] = *index;
+            orientations.push((x, y, *orientation));
+        }
+    }
+    let mut map = Map::from_columns(columns).unwrap();
+    for (x, y, orientation) in orientations {
+        map.set_orientation(x, y, orientation);
+    }
+    Ok(map)
+}
+
+fn check_atlas(tileset: &Tileset, atlas: &RgbaImage) -> Result<(), RenderError> {
+    // in u64 as huge grids would overflow u32
+    let size = tileset.tile_size as u64;
+    if (atlas.width() as u64) < tileset.columns as u64 * size
+        || (atlas.height() as u64) < tileset.rows as u64 * size
+    {
+        return Err(RenderError::AtlasTooSmall {
+            width: atlas.width(),
+            height: atlas.height(),
+        });
+    }
+    Ok(())
+}
+
+// The atlas cell of a tile, turned and mirrored the way the variant is
+fn cell(tileset: &Tileset, atlas: &RgbaImage, index: usize, orientation: Orientation) -> RgbaImage {
+    let size = tileset.tile_size;
+    let index = index as u32;
+    let mut cell = imageops::crop_imm(
+        atlas,
+        index % tileset.columns * size,
+        index / tileset.columns * size,
+        size,
+        size,
+    )
+    .to_image();
+    if orientation.mirrored {
+        imageops::flip_horizontal_in_place(&mut cell);
+    }
+    for _ in 0..orientation.turns {
+        cell = imageops::rotate90(&cell);
+    }
+    cell
+}
+
+// Reads the atlas and writes the rendered map as a PNG, no window or GPU needed
+pub fn render_map_to_png(
+    tileset: &Tileset,
+    atlas: &Path,
+    map: &Map,
+    output: &Path,
+) -> Result<(), RenderError> {
+    let atlas = image::open(atlas)?.into_rgba8();
+    render_map(tileset, &atlas, map)?.save_with_format(output, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+// Reads a PNG drawn with the atlas as a map
+pub fn read_map_from_png(
+    tileset: &Tileset,
+    atlas: &Path,
+    image: &Path,
+) -> Result<Map, RenderError> {
+    let atlas = image::open(atlas)?.into_rgba8();
+    read_map(tileset, &atlas, &image::open(image)?.into_rgba8())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::tileset::plain_tile;
+
+    const A: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const B: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const C: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const D: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const GREY: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+    // Tiles of 2x2 pixels, 0 with a different colour in each corner and 1 all grey
+    fn atlas() -> (Tileset, RgbaImage) {
+        let text = format!(
+            "(atlas: \"tiles.png\", tile_size: 2, columns: 2, rows: 1, tiles: [{}, {}])",
+            plain_tile(0, "Grass", ", symmetry: Some(F)"),
+            plain_tile(1, "Grass", "")
+        );
+        let tileset = Tileset::from_ron(text.as_bytes()).unwrap();
+        let atlas = RgbaImage::from_fn(4, 2, |x, y| match (x, y) {
+            (0, 0) => A,
+            (1, 0) => B,
+            (0, 1) => C,
+            (1, 1) => D,
+            _ => GREY,
+        });
+        (tileset, atlas)
+    }
+
+    #[test]
+    fn draws_cells_turned_and_mirrored() {
+        let (tileset, atlas) = atlas();
+        let mut map = Map::from_columns(vec![vec![0, 0], vec![1, 0]]).unwrap();
+        map.set_orientation(0, 0, Orientation::new(1, false));
+        map.set_orientation(0, 1, Orientation::new(0, true));
+        let image = render_map(&tileset, &atlas, &map).unwrap();
+        assert_eq!(image.dimensions(), (4, 4));
+
+        // the bottom row of cells is at the bottom of the image
+        let pixels = |x: u32, y: u32| {
+            [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| *image.get_pixel(x + dx, y + dy))
+        };
+        // mirrored at the top left, as drawn at the top right
+        assert_eq!(pixels(0, 0), [B, A, D, C]);
+        assert_eq!(pixels(2, 0), [A, B, C, D]);
+        // turned clockwise at the bottom left, grey at the bottom right
+        assert_eq!(pixels(0, 2), [C, A, D, B]);
+        assert_eq!(pixels(2, 2), [GREY; 4]);
+    }
+
+    #[test]
+    fn reads_back_rendered_maps() {
+        let (tileset, atlas) = atlas();
+        // every variant of 0 below a row of 1
+        let mut map = Map::from_columns(vec![vec![0, 0, 1]; 4]).unwrap();
+        for (i, orientation) in Orientation::all().enumerate() {
+            map.set_orientation(i % 4, i / 4, orientation);
+        }
+        let image = render_map(&tileset, &atlas, &map).unwrap();
+        assert_eq!(read_map(&tileset, &atlas, &image).unwrap(), map);
+    }
+
+    #[test]
+    fn rejects_images_that_arent_tiles() {
+        let (tileset, atlas) = atlas();
+        assert!(matches!(
+            read_map(&tileset, &atlas, &RgbaImage::new(3, 2)),
+            Err(RenderError::NotTileGrid {
+                width: 3,
+                height: 2
+            })
+        ));
+        let black = RgbaImage::new(2, 2);
+        assert!(matches!(
+            read_map(&tileset, &atlas, &black),
+            Err(RenderError::UnknownCell { x: 0, y: 0 })
+        ));
+        assert!(matches!(
+            render_map(&tileset, &black, &Map::from_columns(vec![vec![1]]).unwrap()),
+            Err(RenderError::AtlasTooSmall { .. })
+        ));
+        // a grid with more pixels than fit in u32
+        let mut tileset = tileset;
+        tileset.columns = 100000;
+        tileset.rows = 100000;
+        assert!(matches!(
+            render_map(&tileset, &atlas, &Map::from_columns(vec![vec![1]]).unwrap()),
+            Err(RenderError::AtlasTooSmall { .. })
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+use crate::tileset::{Orientation, Tileset};
+
+// Bumped whenever the layout of SavedMap changes
+pub const SAVE_VERSION: u32 = 2;
+
+// Atlas indices that had tiles of their own in version 1 and are now drawn by turning another
+// tile, with that tile and how many quarter turns clockwise draw them
+const REPLACED_TILES: [(usize, usize, u8); 13] = [
+    (13, 4, 3),
+    (15, 4, 1),
+    (24, 4, 2),
+    (34, 32, 1),
+    (36, 35, 1),
+    (45, 46, 1),
+    (51, 50, 1),
+    (52, 32, 3),
+    (54, 32, 2),
+    (55, 46, 3),
+    (56, 46, 2),
+    (60, 50, 3),
+    (61, 50, 2),
+];
+
+// The tile and orientation drawing an atlas index the shipped tileset no longer has a tile for
+pub fn replaced_tile(index: usize) -> Option<(usize, Orientation)> {
+    REPLACED_TILES
+        .iter()
+        .find(|(old, _, _)| *old == index)
+        .map(|&(_, index, turns)| (index, Orientation::new(turns, false)))
+}
+
+// A generated map as written to disk
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SavedMap {
+    pub version: u32,
+    // Asset path of the tileset the indices refer to
+    pub tileset: String,
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    // Atlas indices indexed [x][y]
+    pub tiles: Vec<Vec<usize>>,
+    // Indexed [x][y] like the tiles, empty when every tile is drawn as is. Version 1 saves
+    // don't have them.
+    #[serde(default)]
+    pub orientations: Vec<Vec<Orientation>>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    ParseJson(serde_json::Error),
+    UnsupportedVersion { version: u32 },
+    WrongSize { width: usize, height: usize },
+    UnknownTile { x: usize, y: usize, index: usize },
+    // The cell is turned or mirrored in a way the symmetry of its tile doesn't allow
+    WrongOrientation { x: usize, y: usize },
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "could not access save file: {}", err),
+            SaveError::Serialize(err) => write!(f, "could not write save: {}", err),
+            SaveError::Parse(err) => write!(f, "could not parse save: {}", err),
+            SaveError::ParseJson(err) => write!(f, "could not parse save: {}", err),
+            SaveError::UnsupportedVersion { version } => write!(
+                f,
+                "save has version {}, only versions up to {} are supported",
+                version, SAVE_VERSION
+            ),
+            SaveError::WrongSize { width, height } => {
+                write!(f, "save tiles don't make a {}x{} map", width, height)
+            }
+            SaveError::UnknownTile { x, y, index } => {
+                write!(f, "tile {} at ({}, {}) is not in the tileset", index, x, y)
+            }
+            SaveError::WrongOrientation { x, y } => write!(
+                f,
+                "tile at ({}, {}) is turned or mirrored in a way its tile can't be",
+                x, y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(err: ron::Error) -> Self {
+        SaveError::Serialize(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        SaveError::Parse(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveError::ParseJson(err)
+    }
+}
+
+impl SavedMap {
+    pub fn new(tileset: &str, seed: u64, map: &Map) -> SavedMap {
+        let orientations = (0..map.width())
+            .map(|x| {
+                (0..map.height())
+                    .map(|y| map.orientation(x, y))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let oriented = orientations
+            .iter()
+            .flatten()
+            .any(|orientation| *orientation != Orientation::default());
+        SavedMap {
+            version: SAVE_VERSION,
+            tileset: tileset.to_string(),
+            seed,
+            width: map.width(),
+            height: map.height(),
+            tiles: map.columns(),
+            orientations: if oriented { orientations } else { vec![] },
+        }
+    }
+
+    // The saved tiles as a map, if every tile is in the tileset and can be drawn the way it was
+    // saved. Orientations the tile looks the same under are drawn as its variant.
+    pub fn map(&self, tileset: &Tileset) -> Result<Map, SaveError> {
+        let mut map = self.grid()?;
+        for x in 0..map.width() {
+            for y in 0..map.height() {
+                let index = map[(x, y)];
+                let Some(tile) = tileset.get(index) else {
+                    return Err(SaveError::UnknownTile { x, y, index });
+                };
+                let Some(variant) = tile.variant(map.orientation(x, y)) else {
+                    return Err(SaveError::WrongOrientation { x, y });
+                };
+                map.set_orientation(x, y, variant);
+            }
+        }
+        Ok(map)
+    }
+
+    // The saved tiles as a map, if they have the saved size
+    fn grid(&self) -> Result<Map, SaveError> {
+        let wrong_size = SaveError::WrongSize {
+            width: self.width,
+            height: self.height,
+        };
+        let Some(mut map) = Map::from_columns(self.tiles.clone())
+            .filter(|map| map.width() == self.width && map.height() == self.height)
+        else {
+            return Err(wrong_size);
+        };
+        if self.orientations.is_empty() {
+            return Ok(map);
+        }
+        if self.orientations.len() != self.width
+            || self
+                .orientations
+                .iter()
+                .any(|column| column.len() != self.height)
+        {
+            return Err(wrong_size);
+        }
+        for (x, column) in self.orientations.iter().enumerate() {
+            for (y, orientation) in column.iter().enumerate() {
+                map.set_orientation(x, y, *orientation);
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn to_ron(&self) -> Result<String, SaveError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(text: &str) -> Result<SavedMap, SaveError> {
+        ron::from_str::<SavedMap>(text)?.checked()
+    }
+
+    // What mapgen --format json writes
+    pub fn from_json(text: &str) -> Result<SavedMap, SaveError> {
+        serde_json::from_str::<SavedMap>(text)?.checked()
+    }
+
+    // The map upgraded to the current version, if its version is known and its tiles make a map
+    // of its size
+    fn checked(mut self) -> Result<SavedMap, SaveError> {
+        if !(1..=SAVE_VERSION).contains(&self.version) {
+            return Err(SaveError::UnsupportedVersion {
+                version: self.version,
+            });
+        }
+        if self.version == 1 {
+            self.upgrade();
+        }
+        self.grid()?;
+        Ok(self)
+    }
+
+    // Version 1 is version 2 without orientations, and with a tile for every turn of a tile
+    fn upgrade(&mut self) {
+        let orientations = self
+            .tiles
+            .iter_mut()
+            .map(|column| {
+                column
+                    .iter_mut()
+                    .map(|index| match replaced_tile(*index) {
+                        Some((replacement, orientation)) => {
+                            *index = replacement;
+                            orientation
+                        }
+                        None => Orientation::default(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let oriented = orientations
+            .iter()
+            .flatten()
+            .any(|orientation| *orientation != Orientation::default());
+        self.orientations = if oriented { orientations } else { vec![] };
+        self.version = SAVE_VERSION;
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        Ok(std::fs::write(path, self.to_ron()?)?)
+    }
+
+    pub fn load(path: &Path) -> Result<SavedMap, SaveError> {
+        SavedMap::from_ron(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tileset::load_tileset;
+
+    fn map() -> SavedMap {
+        let tiles = Map::from_columns(vec![vec![11, 13, 36], vec![70, 0, 52]]).unwrap();
+        SavedMap::new("tiles.tileset.ron", 42, &tiles)
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let map = map();
+        let text = map.to_ron().unwrap();
+        assert_eq!(SavedMap::from_ron(&text).unwrap(), map);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let map = map();
+        let path = std::env::temp_dir().join(format!("game-save-{}.ron", std::process::id()));
+        map.save(&path).unwrap();
+        let loaded = SavedMap::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), map);
+    }
+
+    #[test]
+    fn keeps_dimensions() {
+        let map = map();
+        assert_eq!((map.width, map.height), (2, 3));
+    }
+
+    #[test]
+    fn keeps_orientations() {
+        let mut tiles = Map::from_columns(vec![vec![32, 32], vec![35, 70]]).unwrap();
+        tiles.set_orientation(0, 1, Orientation::new(3, false));
+        tiles.set_orientation(1, 0, Orientation::new(1, false));
+        let text = SavedMap::new("tiles.tileset.ron", 42, &tiles)
+            .to_ron()
+            .unwrap();
+        let loaded = SavedMap::from_ron(&text).unwrap();
+        assert_eq!(loaded.map(&load_tileset()).unwrap(), tiles);
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let text = "(version: 1, tileset: \"tiles.tileset.ron\", seed: 42, width: 2, height: 3, \
+                    tiles: [[11, 13, 36], [70, 0, 52]])";
+        let saved = SavedMap::from_ron(text).unwrap();
+        assert_eq!(saved.version, SAVE_VERSION);
+
+        // the turned tiles version 1 had are drawn by turning the tiles they were made from
+        let mut expected = Map::from_columns(vec![vec![11, 4, 35], vec![70, 0, 32]]).unwrap();
+        expected.set_orientation(0, 1, Orientation::new(3, false));
+        expected.set_orientation(0, 2, Orientation::new(1, false));
+        expected.set_orientation(1, 2, Orientation::new(3, false));
+        assert_eq!(saved.map(&load_tileset()).unwrap(), expected);
+    }
+
+    #[test]
+    fn replaced_tiles_are_in_the_tileset() {
+        let tileset = load_tileset();
+        for (old, _, _) in REPLACED_TILES {
+            assert!(tileset.get(old).is_none(), "{} is in the tileset", old);
+            let (index, orientation) = replaced_tile(old).unwrap();
+            let tile = tileset.get(index).unwrap();
+            assert_eq!(tile.variant(orientation), Some(orientation), "tile {}", old);
+        }
+    }
+
+    #[test]
+    fn reads_json() {
+        let map = map();
+        let text = serde_json::to_string_pretty(&map).unwrap();
+        assert_eq!(SavedMap::from_json(&text).unwrap(), map);
+
+        // version 1 saves are upgraded the same way as in ron
+        let text = "{\"version\": 1, \"tileset\": \"tiles.tileset.ron\", \"seed\": 42, \
+                    \"width\": 1, \"height\": 1, \"tiles\": [[52]]}";
+        let saved = SavedMap::from_json(text).unwrap();
+        assert_eq!(saved.version, SAVE_VERSION);
+        assert_eq!(saved.tiles, vec![vec![32]]);
+        assert_eq!(saved.orientations, vec![vec![Orientation::new(3, false)]]);
+        let text = text.replace("\"version\": 1", "\"version\": 3");
+        assert!(matches!(
+            SavedMap::from_json(&text),
+            Err(SaveError::UnsupportedVersion { version: 3 })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut map = map();
+        map.version = SAVE_VERSION + 1;
+        let text = ron::to_string(&map).unwrap();
+        assert!(matches!(
+            SavedMap::from_ron(&text),
+            Err(SaveError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_ragged_tiles() {
+        let mut map = map();
+        map.tiles[1].pop();
+        let text = ron::to_string(&map).unwrap();
+        assert!(matches!(
+            SavedMap::from_ron(&text),
+            Err(SaveError::WrongSize { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_orientations_that_dont_exist() {
+        let text = "(version: 2, tileset: \"tiles.tileset.ron\", seed: 42, width: 1, height: 1, \
+                    tiles: [[32]], orientations: [[(turns: 4, mirrored: false)]])";
+        assert!(matches!(SavedMap::from_ron(text), Err(SaveError::Parse(_))));
+    }
+
+    #[test]
+    fn rejects_orientations_the_tile_cant_have() {
+        let tileset = load_tileset();
+        let mut tiles = Map::from_columns(vec![vec![70, 70]]).unwrap();
+        tiles.set_orientation(0, 1, Orientation::new(1, false));
+        let saved = SavedMap::new("tiles.tileset.ron", 42, &tiles);
+        assert!(matches!(
+            saved.map(&tileset),
+            Err(SaveError::WrongOrientation { x: 0, y: 1 })
+        ));
+
+        // a road turned half way looks the same, so it is drawn as drawn
+        let mut tiles = Map::from_columns(vec![vec![35]]).unwrap();
+        tiles.set_orientation(0, 0, Orientation::new(2, false));
+        let saved = SavedMap::new("tiles.tileset.ron", 42, &tiles);
+        assert_eq!(
+            saved.map(&tileset).unwrap().orientation(0, 0),
+            Orientation::default()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_tiles() {
+        let saved = map();
+        assert!(matches!(
+            saved.map(&load_tileset()),
+            Err(SaveError::UnknownTile {
+                x: 0,
+                y: 1,
+                index: 13
+            })
+        ));
+    }
+}
@@ -0,0 +1,450 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+use crate::save::replaced_tile;
+use crate::tileset::{Orientation, Tileset};
+
+// Tiled stores flips and rotations in the top bits of a gid
+const FLIP_FLAGS: u32 = 0xF000_0000;
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+// Flags Tiled draws each orientation with, indexed [turns][mirrored]
+const ORIENTATION_FLAGS: [[u32; 2]; 4] = [
+    [0, FLIPPED_HORIZONTALLY],
+    [
+        FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY,
+        FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY,
+    ],
+    [
+        FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY,
+        FLIPPED_VERTICALLY,
+    ],
+    [FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY, FLIPPED_DIAGONALLY],
+];
+
+fn orientation_flags(orientation: Orientation) -> u32 {
+    ORIENTATION_FLAGS[orientation.turns as usize][orientation.mirrored as usize]
+}
+
+// None for the flag Tiled uses to turn hexagonal tiles
+fn flags_orientation(flags: u32) -> Option<Orientation> {
+    Orientation::all().find(|orientation| orientation_flags(*orientation) == flags)
+}
+
+// The parts of a Tiled JSON map (TMJ) we read and write
+#[derive(Serialize, Deserialize)]
+struct TmjMap {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    tiledversion: String,
+    orientation: String,
+    #[serde(default)]
+    renderorder: String,
+    width: usize,
+    height: usize,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    nextlayerid: u32,
+    #[serde(default)]
+    nextobjectid: u32,
+    layers: Vec<TmjLayer>,
+    tilesets: Vec<TmjTileset>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<TmjProperty>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TmjLayer {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    width: usize,
+    #[serde(default)]
+    height: usize,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    opacity: f32,
+    #[serde(default)]
+    visible: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(default)]
+    data: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TmjTileset {
+    firstgid: u32,
+    // Set when the tileset lives in its own file instead of being embedded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TmjProperty {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub enum TiledError {
+    Json(serde_json::Error),
+    // The map uses a Tiled feature we can't load, e.g. an infinite map or an external tileset
+    Unsupported(&'static str),
+    NoTileLayer,
+    WrongSize { width: usize, height: usize },
+    // The atlas grid of the map's tileset doesn't match ours
+    WrongGrid { tile_size: u32, columns: u32 },
+    EmptyCell { x: usize, y: usize },
+    FlippedTile { x: usize, y: usize },
+    UnknownTile { x: usize, y: usize, index: usize },
+}
+
+impl std::fmt::Display for TiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TiledError::Json(err) => write!(f, "could not parse Tiled map: {}", err),
+            TiledError::Unsupported(feature) => write!(f, "{} are not supported", feature),
+            TiledError::NoTileLayer => write!(f, "Tiled map has no tile layer"),
+            TiledError::WrongSize { width, height } => {
+                write!(f, "tile layer data doesn't fill a {}x{} map", width, height)
+            }
+            TiledError::WrongGrid { tile_size, columns } => write!(
+                f,
+                "Tiled tileset has {}px tiles in {} columns, which doesn't match the atlas",
+                tile_size, columns
+            ),
+            TiledError::EmptyCell { x, y } => write!(f, "no tile at ({}, {})", x, y),
+            TiledError::FlippedTile { x, y } => write!(
+                f,
+                "tile at ({}, {}) is flipped or rotated in a way its tile can't be",
+                x, y
+            ),
+            TiledError::UnknownTile { x, y, index } => {
+                write!(f, "tile {} at ({}, {}) is not in the tileset", index, x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+impl From<serde_json::Error> for TiledError {
+    fn from(err: serde_json::Error) -> Self {
+        TiledError::Json(err)
+    }
+}
+
+// The path Tiled finds the atlas at from a map written to output, which looks for it relative
+// to the map file. Without an output, e.g. for standard output, the map is read from here.
+pub fn atlas_path(atlas: &Path, output: Option<&Path>) -> std::io::Result<PathBuf> {
+    let dir = output.and_then(|output| output.parent());
+    match dir.filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => relative_path(atlas, dir),
+        None => Ok(atlas.to_path_buf()),
+    }
+}
+
+// The path to reach path from dir, neither has to exist
+fn relative_path(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let (path, dir) = (std::path::absolute(path)?, std::path::absolute(dir)?);
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    Ok(relative)
+}
+
+// Writes a map as a TMJ map using the tileset atlas. `image` is the path of the
+// atlas relative to where the TMJ file will be saved, as atlas_path gives it.
+pub fn export_tmj(
+    tileset: &Tileset,
+    map: &Map,
+    seed: u64,
+    image: &str,
+) -> Result<String, TiledError> {
+    let (width, height) = (map.width(), map.height());
+    // Tiled rows go top to bottom, our y goes up
+    let data = (0..height)
+        .rev()
+        .flat_map(|y| {
+            (0..width)
+                .map(move |x| (map[(x, y)] as u32 + 1) | orientation_flags(map.orientation(x, y)))
+        })
+        .collect();
+
+    let tmj = TmjMap {
+        kind: "map".to_string(),
+        version: "1.10".to_string(),
+        tiledversion: "1.10.2".to_string(),
+        orientation: "orthogonal".to_string(),
+        renderorder: "right-down".to_string(),
+        width,
+        height,
+        tilewidth: tileset.tile_size,
+        tileheight: tileset.tile_size,
+        infinite: false,
+        nextlayerid: 2,
+        nextobjectid: 1,
+        layers: vec![TmjLayer {
+            id: 1,
+            name: "Tiles".to_string(),
+            kind: "tilelayer".to_string(),
+            width,
+            height,
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            visible: true,
+            encoding: None,
+            data: Some(data),
+        }],
+        tilesets: vec![TmjTileset {
+            firstgid: 1,
+            source: None,
+            name: "tiles".to_string(),
+            image: image.to_string(),
+            imagewidth: tileset.columns * tileset.tile_size,
+            imageheight: tileset.rows * tileset.tile_size,
+            tilewidth: tileset.tile_size,
+            tileheight: tileset.tile_size,
+            tilecount: tileset.columns * tileset.rows,
+            columns: tileset.columns,
+            margin: 0,
+            spacing: 0,
+        }],
+        // a string since Tiled ints can't hold every u64
+        properties: vec![TmjProperty {
+            name: "seed".to_string(),
+            kind: "string".to_string(),
+            value: seed.to_string().into(),
+        }],
+    };
+    Ok(serde_json::to_string_pretty(&tmj)?)
+}
+
+// Reads the first tile layer of a TMJ map made with the tileset atlas. Returns the map and the
+// seed it was generated with, if it was exported by export_tmj.
+pub fn import_tmj(
+    text: &str,
+    tileset: &Tileset,
+) -> Result<(Map, Option<u64>), TiledError> {
+    let tmj: TmjMap = serde_json::from_str(text)?;
+
+    if tmj.orientation != "orthogonal" {
+        return Err(TiledError::Unsupported("non orthogonal maps"));
+    }
+    if tmj.infinite {
+        return Err(TiledError::Unsupported("infinite maps"));
+    }
+    let Some(layer) = tmj.layers.iter().find(|layer| layer.kind == "tilelayer") else {
+        return Err(TiledError::NoTileLayer);
+    };
+    if layer
+        .encoding
+        .as_ref()
+        .is_some_and(|encoding| encoding != "csv")
+    {
+        return Err(TiledError::Unsupported("encoded tile layers"));
+    }
+    let (width, height) = (tmj.width, tmj.height);
+    let data = layer.data.as_deref().unwrap_or_default();
+    if data.len() != width * height {
+        return Err(TiledError::WrongSize { width, height });
+    }
+
+    let mut columns = vec![vec![0; height]; width];
+    let mut orientations = vec![];
+    for (i, &gid) in data.iter().enumerate() {
+        let (x, y) = (i % width, height - 1 - i / width);
+        let Some(mut orientation) = flags_orientation(gid & FLIP_FLAGS) else {
+            return Err(TiledError::FlippedTile { x, y });
+        };
+        let gid = gid & !FLIP_FLAGS;
+        if gid == 0 {
+            return Err(TiledError::EmptyCell { x, y });
+        }
+        // the tileset a gid belongs to is the one with the highest firstgid not above it
+        let Some(tiles) = tmj
+            .tilesets
+            .iter()
+            .filter(|tiles| tiles.firstgid <= gid)
+            .max_by_key(|tiles| tiles.firstgid)
+        else {
+            return Err(TiledError::EmptyCell { x, y });
+        };
+        if tiles.source.is_some() {
+            return Err(TiledError::Unsupported("external tilesets"));
+        }
+        if tiles.tilewidth != tileset.tile_size || tiles.columns != tileset.columns {
+            return Err(TiledError::WrongGrid {
+                tile_size: tiles.tilewidth,
+                columns: tiles.columns,
+            });
+        }
+        let mut index = (gid - tiles.firstgid) as usize;
+        // maps exported before the tile was drawn by turning another one
+        if let (None, Some((replacement, turned))) = (tileset.get(index), replaced_tile(index)) {
+            index = replacement;
+            orientation = turned.then(orientation);
+        }
+        let Some(tile) = tileset.get(index) else {
+            return Err(TiledError::UnknownTile { x, y, index });
+        };
+        // flips the tile looks the same under are fine, they are drawn as its variant
+        let Some(variant) = tile.variant(orientation) else {
+            return Err(TiledError::FlippedTile { x, y });
+        };
+        columns[x][y] = index;
+        orientations.push((x, y, variant));
+    }
+
+    let seed = tmj
+        .properties
+        .iter()
+        .find(|property| property.name == "seed")
+        .and_then(|property| property.value.as_str())
+        .and_then(|seed| seed.parse().ok());
+    let mut map = Map::from_columns(columns).ok_or(TiledError::WrongSize { width, height })?;
+    for (x, y, orientation) in orientations {
+        map.set_orientation(x, y, orientation);
+    }
+    Ok((map, seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tileset::{load_tileset, plain_tile, test_tileset};
+
+    // A map one row high made with the shipped atlas
+    fn tmj(data: &[u32]) -> String {
+        format!(
+            "{{\"type\": \"map\", \"orientation\": \"orthogonal\", \"width\": {}, \"height\": 1, \
+             \"tilewidth\": 32, \"tileheight\": 32, \
+             \"layers\": [{{\"type\": \"tilelayer\", \"data\": {:?}}}], \
+             \"tilesets\": [{{\"firstgid\": 1, \"tilewidth\": 32, \"columns\": 10}}]}}",
+            data.len(),
+            data
+        )
+    }
+
+    #[test]
+    fn imports_tiles_that_are_now_turned() {
+        // road turn 34 as exported before it was drawn by turning 32, and road 36 mirrored
+        let text = tmj(&[35, 37 | FLIPPED_HORIZONTALLY]);
+        let (map, seed) = import_tmj(&text, &load_tileset()).unwrap();
+        assert_eq!(seed, None);
+        let mut expected = Map::from_columns(vec![vec![32], vec![35]]).unwrap();
+        expected.set_orientation(0, 0, Orientation::new(1, false));
+        expected.set_orientation(1, 0, Orientation::new(1, false));
+        assert_eq!(map, expected);
+    }
+
+    #[test]
+    fn round_trips_oriented_cells() {
+        // a tile that looks different every way, turned and mirrored all eight ways
+        let tileset = test_tileset(&[plain_tile(7, "Grass", ", symmetry: Some(F)")]);
+        let mut map = Map::from_columns(vec![vec![7; 2]; 4]).unwrap();
+        for (i, orientation) in Orientation::all().enumerate() {
+            map.set_orientation(i % 4, i / 4, orientation);
+        }
+
+        let text = export_tmj(&tileset, &map, 42, "tiles.png").unwrap();
+        assert_eq!(import_tmj(&text, &tileset).unwrap(), (map, Some(42)));
+        // Tiled turns a tile clockwise by flipping it diagonally then horizontally
+        assert_eq!(
+            orientation_flags(Orientation::new(1, false)),
+            FLIPPED_DIAGONALLY | FLIPPED_HORIZONTALLY
+        );
+    }
+
+    #[test]
+    fn rejects_maps_it_cant_read() {
+        let tileset = load_tileset();
+        assert!(matches!(
+            import_tmj(&tmj(&[71, 0]), &tileset),
+            Err(TiledError::EmptyCell { x: 1, y: 0 })
+        ));
+
+        let small = tmj(&[71]).replace(
+            "\"tilewidth\": 32, \"columns\"",
+            "\"tilewidth\": 16, \"columns\"",
+        );
+        assert!(matches!(
+            import_tmj(&small, &tileset),
+            Err(TiledError::WrongGrid {
+                tile_size: 16,
+                columns: 10
+            })
+        ));
+
+        let external = tmj(&[71]).replace(
+            "\"firstgid\": 1,",
+            "\"firstgid\": 1, \"source\": \"tiles.tsj\",",
+        );
+        assert!(matches!(
+            import_tmj(&external, &tileset),
+            Err(TiledError::Unsupported("external tilesets"))
+        ));
+    }
+
+    #[test]
+    fn finds_the_atlas_from_the_map() {
+        let atlas = Path::new("assets/tiles.png");
+        let from = |output: Option<&str>| atlas_path(atlas, output.map(Path::new)).unwrap();
+        assert_eq!(from(None), atlas);
+        assert_eq!(from(Some("map.tmj")), atlas);
+        assert_eq!(from(Some("maps/map.tmj")), Path::new("../assets/tiles.png"));
+        assert_eq!(from(Some("assets/maps/map.tmj")), Path::new("../tiles.png"));
+        assert_eq!(
+            atlas_path(Path::new("/a/b/tiles.png"), Some(Path::new("/a/c/map.tmj"))).unwrap(),
+            Path::new("../b/tiles.png")
+        );
+    }
+}
@@ -0,0 +1,732 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "bevy")]
+use bevy::asset::{Asset, AssetLoader, LoadContext, io::Reader};
+#[cfg(feature = "bevy")]
+use bevy::reflect::TypePath;
+use serde::{Deserialize, Serialize};
+
+use crate::map::Direction;
+
+#[derive(Serialize, Deserialize, Hash, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum TileKind {
+    Water,
+    Grass,
+    Forest,
+    Road,
+    Crossroad,
+    Roadturn,
+    Roadend,
+}
+
+impl std::str::FromStr for TileKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TileKind, String> {
+        match name.to_lowercase().as_str() {
+            "water" => Ok(TileKind::Water),
+            "grass" => Ok(TileKind::Grass),
+            "forest" => Ok(TileKind::Forest),
+            "road" => Ok(TileKind::Road),
+            "crossroad" => Ok(TileKind::Crossroad),
+            "roadturn" => Ok(TileKind::Roadturn),
+            "roadend" => Ok(TileKind::Roadend),
+            _ => Err(format!("unknown tile kind {}", name)),
+        }
+    }
+}
+
+// How a variant is drawn from its tile: mirrored left to right first if mirrored, then turned
+// clockwise by quarter turns
+#[derive(
+    Serialize, Deserialize, Default, Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug,
+)]
+#[serde(try_from = "OrientationFile")]
+pub struct Orientation {
+    pub turns: u8,
+    pub mirrored: bool,
+}
+
+// An orientation as written, before the turns are checked
+#[derive(Deserialize)]
+struct OrientationFile {
+    turns: u8,
+    mirrored: bool,
+}
+
+impl TryFrom<OrientationFile> for Orientation {
+    type Error = String;
+
+    fn try_from(file: OrientationFile) -> Result<Orientation, String> {
+        if file.turns >= 4 {
+            return Err(format!(
+                "{} turns, a tile turns at most 3 times",
+                file.turns
+            ));
+        }
+        Ok(Orientation::new(file.turns, file.mirrored))
+    }
+}
+
+impl Orientation {
+    pub fn new(turns: u8, mirrored: bool) -> Orientation {
+        Orientation {
+            turns: turns % 4,
+            mirrored,
+        }
+    }
+
+    // Every turn of the tile and of its mirror image
+    pub fn all() -> impl Iterator<Item = Orientation> {
+        (0..4).flat_map(|turns| [false, true].map(|mirrored| Orientation::new(turns, mirrored)))
+    }
+
+    // Doing self and then other
+    pub fn then(self, other: Orientation) -> Orientation {
+        // mirroring after a turn is the same as the opposite turn after mirroring
+        let turns = if other.mirrored {
+            other.turns + 4 - self.turns
+        } else {
+            other.turns + self.turns
+        };
+        Orientation::new(turns, self.mirrored != other.mirrored)
+    }
+
+    // The orientation undoing this one
+    pub fn inverse(self) -> Orientation {
+        match Orientation::all().find(|other| self.then(*other) == Orientation::default()) {
+            Some(inverse) => inverse,
+            None => unreachable!("every orientation can be undone"),
+        }
+    }
+}
+
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.mirrored, self.turns) {
+            (false, 0) => write!(f, "as drawn"),
+            (false, turns) => write!(f, "turned {} degrees", turns as u32 * 90),
+            (true, 0) => write!(f, "mirrored"),
+            (true, turns) => write!(f, "mirrored and turned {} degrees", turns as u32 * 90),
+        }
+    }
+}
+
+// Turns and mirrors a tile looks the same under, they decide which variants of it the
+// generator uses on top of the tile as drawn
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Symmetry {
+    // The same turned or mirrored any way, e.g. plain grass
+    X,
+    // The same turned half way or mirrored, e.g. a straight road
+    I,
+    // The same mirrored then turned three quarters, e.g. a turn joining the bottom and right edges
+    L,
+    // The same mirrored, e.g. a road ending at the bottom edge
+    T,
+    // The same turned half way, e.g. a line from the top left to the bottom right corner
+    Diagonal,
+    // Different every way, all eight variants are used
+    F,
+}
+
+impl Symmetry {
+    // Orientations of the variants that look different from each other
+    fn orientations(self) -> Vec<Orientation> {
+        let turns = match self {
+            Symmetry::X => 1,
+            Symmetry::I | Symmetry::Diagonal => 2,
+            Symmetry::L | Symmetry::T | Symmetry::F => 4,
+        };
+        let mut orientations = (0..turns)
+            .map(|turns| Orientation::new(turns, false))
+            .collect::<Vec<_>>();
+        if self == Symmetry::F {
+            orientations.extend((0..4).map(|turns| Orientation::new(turns, true)));
+        }
+        orientations
+    }
+
+    // Orientations that leave the tile looking the same
+    fn unchanged(self) -> Vec<Orientation> {
+        match self {
+            Symmetry::X => Orientation::all().collect(),
+            Symmetry::I => Orientation::all()
+                .filter(|orientation| orientation.turns % 2 == 0)
+                .collect(),
+            Symmetry::L => vec![Orientation::default(), Orientation::new(3, true)],
+            Symmetry::T => vec![Orientation::default(), Orientation::new(0, true)],
+            Symmetry::Diagonal => vec![
+                Orientation::default(),
+                Orientation::new(2, false),
+                Orientation::new(1, true),
+                Orientation::new(3, true),
+            ],
+            Symmetry::F => vec![Orientation::default()],
+        }
+    }
+}
+
+// A variant that may sit next to a tile
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+pub struct Neighbour {
+    pub index: usize,
+    // Any orientation drawing the variant, not just the one it is numbered by
+    #[serde(default, skip_serializing_if = "is_as_drawn")]
+    pub orientation: Orientation,
+}
+
+fn is_as_drawn(orientation: &Orientation) -> bool {
+    *orientation == Orientation::default()
+}
+
+// Tiles an allow or deny list names, whichever way they are turned
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Adjacent {
+    Kind(TileKind),
+    // Atlas index
+    Tile(usize),
+}
+
+impl Adjacent {
+    pub fn matches(self, tile: &Tile) -> bool {
+        match self {
+            Adjacent::Kind(kind) => tile.kind == kind,
+            Adjacent::Tile(index) => tile.index == index,
+        }
+    }
+}
+
+// A list for each side of a tile, sides left out are empty
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Sides<T> {
+    #[serde(default = "Vec::new")]
+    pub top: Vec<T>,
+    #[serde(default = "Vec::new")]
+    pub bottom: Vec<T>,
+    #[serde(default = "Vec::new")]
+    pub left: Vec<T>,
+    #[serde(default = "Vec::new")]
+    pub right: Vec<T>,
+}
+
+impl<T> Default for Sides<T> {
+    fn default() -> Self {
+        Sides {
+            top: vec![],
+            bottom: vec![],
+            left: vec![],
+            right: vec![],
+        }
+    }
+}
+
+impl<T> Sides<T> {
+    pub fn side(&self, direction: Direction) -> &[T] {
+        match direction {
+            Direction::Top => &self.top,
+            Direction::Bottom => &self.bottom,
+            Direction::Left => &self.left,
+            Direction::Right => &self.right,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top.is_empty()
+            && self.bottom.is_empty()
+            && self.left.is_empty()
+            && self.right.is_empty()
+    }
+
+    // The lists moved to the sides the orientation puts them on
+    fn oriented(self, orientation: Orientation) -> Sides<T> {
+        let Sides {
+            mut top,
+            mut bottom,
+            mut left,
+            mut right,
+        } = self;
+        if orientation.mirrored {
+            std::mem::swap(&mut left, &mut right);
+        }
+        for _ in 0..orientation.turns {
+            (top, right, bottom, left) = (left, top, right, bottom);
+        }
+        Sides {
+            top,
+            bottom,
+            left,
+            right,
+        }
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.top
+            .iter_mut()
+            .chain(&mut self.bottom)
+            .chain(&mut self.left)
+            .chain(&mut self.right)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Tile {
+    // Index of the tile in the texture atlas
+    pub index: usize,
+    pub kind: TileKind,
+    // Relative chance of being picked when a cell collapses, for each variant
+    pub weight: f32,
+    // Horizontal edges go left to right, vertical ones top to bottom
+    pub top: (TileKind, TileKind),
+    pub bottom: (TileKind, TileKind),
+    pub left: (TileKind, TileKind),
+    pub right: (TileKind, TileKind),
+    // Without one only the tile as drawn is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symmetry: Option<Symmetry>,
+    // When set only these variants fit next to the tile, instead of the ones with matching
+    // edges. The edges are then only used against the border. Made by learn::learn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub neighbours: Option<Sides<Neighbour>>,
+    // On top of fitting, only these tiles may be on a side, e.g. water left and right of a
+    // bridge. An empty side allows anything.
+    #[serde(default, skip_serializing_if = "Sides::is_empty")]
+    pub allow: Sides<Adjacent>,
+    // Tiles that may never be on a side, even when they fit
+    #[serde(default, skip_serializing_if = "Sides::is_empty")]
+    pub deny: Sides<Adjacent>,
+}
+
+impl Tile {
+    // Orientations of every variant the generator uses, the tile as drawn first
+    pub fn orientations(&self) -> Vec<Orientation> {
+        match self.symmetry {
+            Some(symmetry) => symmetry.orientations(),
+            None => vec![Orientation::default()],
+        }
+    }
+
+    // The variant that looks like the tile drawn with the given orientation, if there is one
+    pub fn variant(&self, orientation: Orientation) -> Option<Orientation> {
+        let unchanged = match self.symmetry {
+            Some(symmetry) => symmetry.unchanged(),
+            None => vec![Orientation::default()],
+        };
+        self.orientations().into_iter().find(|variant| {
+            unchanged
+                .iter()
+                .any(|same| same.then(*variant) == orientation)
+        })
+    }
+
+    // The tile with its edges and adjacency lists moved to where the orientation puts them
+    pub fn oriented(&self, orientation: Orientation) -> Tile {
+        let reversed = |(a, b): (TileKind, TileKind)| (b, a);
+        let mut tile = self.clone();
+        if orientation.mirrored {
+            (tile.top, tile.bottom) = (reversed(tile.top), reversed(tile.bottom));
+            (tile.left, tile.right) = (tile.right, tile.left);
+        }
+        for _ in 0..orientation.turns {
+            (tile.top, tile.right, tile.bottom, tile.left) = (
+                reversed(tile.left),
+                tile.top,
+                reversed(tile.right),
+                tile.bottom,
+            );
+        }
+        tile.neighbours = tile.neighbours.map(|neighbours| {
+            let mut neighbours = neighbours.oriented(orientation);
+            // the neighbours turn along with the tile
+            for neighbour in neighbours.iter_mut() {
+                neighbour.orientation = neighbour.orientation.then(orientation);
+            }
+            neighbours
+        });
+        tile.allow = tile.allow.oriented(orientation);
+        tile.deny = tile.deny.oriented(orientation);
+        tile
+    }
+}
+
+// The tileset file as written on disk, before validation
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TilesetFile {
+    atlas: String,
+    pub(crate) tile_size: u32,
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+    pub(crate) tiles: Vec<Tile>,
+}
+
+#[cfg_attr(feature = "bevy", derive(Asset, TypePath))]
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    // Path of the texture atlas, relative to the assets folder
+    pub atlas: String,
+    // Size in pixels of one square cell of the atlas
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    // Map from index in the texture atlas to the Tile info, ordered so generation is reproducible
+    tiles: BTreeMap<usize, Tile>,
+}
+
+#[derive(Debug)]
+pub enum TilesetError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Empty,
+    EmptyGrid,
+    DuplicateTile { index: usize },
+    OutsideAtlas { index: usize, columns: u32, rows: u32 },
+    InvalidWeight { index: usize, weight: f32 },
+}
+
+impl std::fmt::Display for TilesetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TilesetError::Io(err) => write!(f, "could not read tileset: {}", err),
+            TilesetError::Parse(err) => write!(f, "could not parse tileset: {}", err),
+            TilesetError::Empty => write!(f, "tileset has no tiles"),
+            TilesetError::EmptyGrid => write!(f, "tileset atlas grid has no cells"),
+            TilesetError::DuplicateTile { index } => {
+                write!(f, "tile {} is defined more than once", index)
+            }
+            TilesetError::OutsideAtlas {
+                index,
+                columns,
+                rows,
+            } => write!(
+                f,
+                "tile {} is outside the {}x{} atlas grid",
+                index, columns, rows
+            ),
+            TilesetError::InvalidWeight { index, weight } => write!(
+                f,
+                "tile {} has weight {}, weights must be finite and not negative",
+                index, weight
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TilesetError {}
+
+impl From<std::io::Error> for TilesetError {
+    fn from(err: std::io::Error) -> Self {
+        TilesetError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for TilesetError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        TilesetError::Parse(err)
+    }
+}
+
+impl Tileset {
+    pub fn from_ron(bytes: &[u8]) -> Result<Tileset, TilesetError> {
+        let file: TilesetFile = ron::de::from_bytes(bytes)?;
+
+        if file.tiles.is_empty() {
+            return Err(TilesetError::Empty);
+        }
+        if file.tile_size == 0 || file.columns == 0 || file.rows == 0 {
+            return Err(TilesetError::EmptyGrid);
+        }
+
+        let mut tiles = BTreeMap::new();
+        for tile in file.tiles {
+            let index = tile.index;
+            if index >= file.columns as usize * file.rows as usize {
+                return Err(TilesetError::OutsideAtlas {
+                    index,
+                    columns: file.columns,
+                    rows: file.rows,
+                });
+            }
+            if !tile.weight.is_finite() || tile.weight < 0.0 {
+                return Err(TilesetError::InvalidWeight {
+                    index,
+                    weight: tile.weight,
+                });
+            }
+            if tiles.insert(index, tile).is_some() {
+                return Err(TilesetError::DuplicateTile { index });
+            }
+        }
+
+        Ok(Tileset {
+            atlas: file.atlas,
+            tile_size: file.tile_size,
+            columns: file.columns,
+            rows: file.rows,
+            tiles,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Tile> {
+        self.tiles.get(&index)
+    }
+
+    // Every tile, in ascending atlas index order
+    pub fn tiles(&self) -> impl Iterator<Item = &Tile> {
+        self.tiles.values()
+    }
+
+    // The same tileset with other tiles, e.g. ones with learned neighbours
+    pub(crate) fn with_tiles(&self, tiles: impl IntoIterator<Item = Tile>) -> Tileset {
+        Tileset {
+            atlas: self.atlas.clone(),
+            tile_size: self.tile_size,
+            columns: self.columns,
+            rows: self.rows,
+            tiles: tiles.into_iter().map(|tile| (tile.index, tile)).collect(),
+        }
+    }
+
+    // Written the way from_ron reads it, to commit alongside the atlas
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        let file = TilesetFile {
+            atlas: self.atlas.clone(),
+            tile_size: self.tile_size,
+            columns: self.columns,
+            rows: self.rows,
+            tiles: self.tiles.values().cloned().collect(),
+        };
+        // neighbour lists on one line each, the file is long enough already
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default().depth_limit(4))
+    }
+}
+
+// Loads `*.tileset.ron` files through the AssetServer
+#[cfg(feature = "bevy")]
+#[derive(Default)]
+pub struct TilesetLoader;
+
+#[cfg(feature = "bevy")]
+impl AssetLoader for TilesetLoader {
+    type Asset = Tileset;
+    type Settings = ();
+    type Error = TilesetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Tileset, TilesetError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Tileset::from_ron(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tileset.ron"]
+    }
+}
+
+// The tileset the game ships with, for tests
+#[cfg(test)]
+pub(crate) fn load_tileset() -> Tileset {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../assets/tiles.tileset.ron");
+    Tileset::from_ron(&std::fs::read(path).unwrap()).unwrap()
+}
+
+// A tileset with a 10x10 grid of 32 pixel cells and the given tiles, for tests
+#[cfg(test)]
+pub(crate) fn test_tileset(tiles: &[String]) -> Tileset {
+    let text = format!(
+        "(atlas: \"tiles.png\", tile_size: 32, columns: 10, rows: 10, tiles: [{}])",
+        tiles.join(", ")
+    );
+    Tileset::from_ron(text.as_bytes()).unwrap()
+}
+
+// A tile of weight 1 with grass on every edge, rest goes after the edges, e.g. its symmetry
+#[cfg(test)]
+pub(crate) fn plain_tile(index: usize, kind: &str, rest: &str) -> String {
+    format!(
+        "(index: {}, kind: {}, weight: 1.0, top: (Grass, Grass), bottom: (Grass, Grass), \
+         left: (Grass, Grass), right: (Grass, Grass){})",
+        index, kind, rest
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tileset file with a 4x4 atlas grid and the given tiles, as index and weight
+    fn tileset(tile_size: u32, tiles: &[(usize, &str)]) -> Result<Tileset, TilesetError> {
+        let tiles = tiles
+            .iter()
+            .map(|(index, weight)| {
+                format!(
+                    "(index: {}, kind: Grass, weight: {}, top: (Grass, Grass), \
+                     bottom: (Grass, Grass), left: (Grass, Grass), right: (Grass, Grass))",
+                    index, weight
+                )
+            })
+            .collect::<Vec<_>>();
+        let text = format!(
+            "(atlas: \"tiles.png\", tile_size: {}, columns: 4, rows: 4, tiles: [{}])",
+            tile_size,
+            tiles.join(", ")
+        );
+        Tileset::from_ron(text.as_bytes())
+    }
+
+    // The error, which has to name the tile
+    fn error_naming(index: usize, result: Result<Tileset, TilesetError>) -> TilesetError {
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains(&format!("tile {} ", index)),
+            "{} doesn't name tile {}",
+            err,
+            index
+        );
+        err
+    }
+
+    #[test]
+    fn loads_valid_tilesets() {
+        let tileset = tileset(32, &[(0, "1.0"), (15, "0.0")]).unwrap();
+        assert_eq!(tileset.tiles().count(), 2);
+        assert_eq!(tileset.get(15).unwrap().weight, 0.0);
+    }
+
+    #[test]
+    fn loads_grids_with_more_cells_than_fit_in_u32() {
+        let text = format!(
+            "(atlas: \"tiles.png\", tile_size: 32, columns: 100000, rows: 100000, tiles: [{}])",
+            plain_tile(9999999999, "Grass", "")
+        );
+        let tileset = Tileset::from_ron(text.as_bytes()).unwrap();
+        assert!(tileset.get(9999999999).is_some());
+    }
+
+    #[test]
+    fn names_the_tile_at_fault() {
+        assert!(matches!(
+            error_naming(3, tileset(32, &[(3, "1.0"), (5, "1.0"), (3, "2.0")])),
+            TilesetError::DuplicateTile { index: 3 }
+        ));
+        assert!(matches!(
+            error_naming(16, tileset(32, &[(0, "1.0"), (16, "1.0")])),
+            TilesetError::OutsideAtlas {
+                index: 16,
+                columns: 4,
+                rows: 4
+            }
+        ));
+        assert!(matches!(
+            error_naming(7, tileset(32, &[(7, "-1.0")])),
+            TilesetError::InvalidWeight { index: 7, .. }
+        ));
+        assert!(matches!(
+            error_naming(2, tileset(32, &[(1, "1.0"), (2, "inf")])),
+            TilesetError::InvalidWeight { index: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_tilesets() {
+        assert!(matches!(tileset(32, &[]), Err(TilesetError::Empty)));
+        assert!(matches!(
+            tileset(0, &[(0, "1.0")]),
+            Err(TilesetError::EmptyGrid)
+        ));
+    }
+
+    #[test]
+    fn turns_edges_clockwise() {
+        use TileKind::*;
+        // the road turn joins the bottom and right edges as drawn
+        let turn = load_tileset()
+            .get(32)
+            .unwrap()
+            .oriented(Orientation::new(1, false));
+        assert_eq!(turn.top, (Grass, Grass));
+        assert_eq!(turn.right, (Grass, Grass));
+        assert_eq!(turn.bottom, (Road, Road));
+        assert_eq!(turn.left, (Road, Road));
+
+        // edges go left to right and top to bottom, so turning reverses some of them
+        let tile: Tile = ron::from_str(
+            "(index: 0, kind: Grass, weight: 1.0, top: (Grass, Water), bottom: (Road, Forest), \
+             left: (Water, Road), right: (Forest, Grass))",
+        )
+        .unwrap();
+        let turned = tile.oriented(Orientation::new(1, false));
+        assert_eq!(turned.top, (Road, Water));
+        assert_eq!(turned.right, (Grass, Water));
+        assert_eq!(turned.bottom, (Grass, Forest));
+        assert_eq!(turned.left, (Road, Forest));
+        let mirrored = tile.oriented(Orientation::new(0, true));
+        assert_eq!(mirrored.top, (Water, Grass));
+        assert_eq!(mirrored.left, (Forest, Grass));
+
+        // orienting twice is orienting once by both
+        for first in Orientation::all() {
+            for second in Orientation::all() {
+                assert_eq!(
+                    tile.oriented(first).oriented(second),
+                    tile.oriented(first.then(second)),
+                    "{} then {}",
+                    first,
+                    second
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn undoes_orientations() {
+        for orientation in Orientation::all() {
+            assert_eq!(
+                orientation.then(orientation.inverse()),
+                Orientation::default()
+            );
+            assert_eq!(
+                orientation.inverse().then(orientation),
+                Orientation::default()
+            );
+        }
+        assert_eq!(
+            Orientation::new(1, false).inverse(),
+            Orientation::new(3, false)
+        );
+        assert_eq!(
+            Orientation::new(1, true).inverse(),
+            Orientation::new(1, true)
+        );
+    }
+
+    #[test]
+    fn finds_the_variant_drawn() {
+        let tileset = load_tileset();
+        // the road turn mirrored joins the bottom and left edges, like it turned once
+        let turn = tileset.get(32).unwrap();
+        assert_eq!(
+            turn.variant(Orientation::new(0, true)),
+            Some(Orientation::new(1, false))
+        );
+        assert_eq!(
+            turn.variant(Orientation::new(3, true)),
+            Some(Orientation::default())
+        );
+        assert_eq!(
+            turn.variant(Orientation::new(2, false)),
+            Some(Orientation::new(2, false))
+        );
+        // grass has no symmetry, so it's only drawn as drawn
+        let grass = tileset.get(70).unwrap();
+        assert_eq!(
+            grass.variant(Orientation::default()),
+            Some(Orientation::default())
+        );
+        assert_eq!(grass.variant(Orientation::new(1, false)), None);
+    }
+}
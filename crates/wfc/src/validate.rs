@@ -0,0 +1,397 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::map::{DIRECTIONS, Direction};
+use crate::tileset::{Adjacent, Orientation, Tile, TileKind, Tileset, TilesetError, TilesetFile};
+
+// Something wrong or suspicious about a tile of a tileset
+#[derive(Clone, PartialEq, Debug)]
+pub enum Issue {
+    // No variant fits this edge or is in the neighbour list for it, the variant can't have a
+    // neighbour on that side
+    UnmatchedEdge {
+        index: usize,
+        orientation: Orientation,
+        direction: Direction,
+        edge: (TileKind, TileKind),
+    },
+    // Every edge fits some variant, but only variants that can never be placed
+    Unreachable {
+        index: usize,
+        orientation: Orientation,
+    },
+    // The edges don't look the same under every turn and mirror the symmetry says they do
+    WrongSymmetry { index: usize },
+    // A neighbour, allow or deny list names a tile the tileset doesn't have
+    UnknownNeighbour { index: usize, neighbour: usize },
+    // Only picked when nothing else fits
+    ZeroWeight { index: usize },
+    InvalidWeight { index: usize, weight: f32 },
+    DuplicateIndex { index: usize },
+    OutsideAtlas { index: usize, columns: u32, rows: u32 },
+    // The atlas has no cells for tiles to be in
+    EmptyGrid { tile_size: u32, columns: u32, rows: u32 },
+}
+
+impl Issue {
+    // Warnings may be intended, errors never are
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::ZeroWeight { .. })
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Issue::UnmatchedEdge {
+                index,
+                orientation,
+                direction,
+                edge,
+            } => write!(
+                f,
+                "tile {} {} has {} edge {:?} which no tile fits",
+                index, orientation, direction, edge
+            ),
+            Issue::Unreachable { index, orientation } => write!(
+                f,
+                "tile {} {} can never be placed, the tiles fitting it can't be either",
+                index, orientation
+            ),
+            Issue::WrongSymmetry { index } => write!(
+                f,
+                "tile {} doesn't have the symmetry it is declared with",
+                index
+            ),
+            Issue::UnknownNeighbour { index, neighbour } => write!(
+                f,
+                "tile {} lists tile {} next to it, which is not in the tileset",
+                index, neighbour
+            ),
+            Issue::ZeroWeight { index } => write!(
+                f,
+                "tile {} has weight 0 and is only picked when nothing else fits",
+                index
+            ),
+            Issue::InvalidWeight { index, weight } => write!(
+                f,
+                "tile {} has weight {}, weights must be finite and not negative",
+                index, weight
+            ),
+            Issue::DuplicateIndex { index } => {
+                write!(f, "tile {} is defined more than once", index)
+            }
+            Issue::OutsideAtlas {
+                index,
+                columns,
+                rows,
+            } => write!(
+                f,
+                "tile {} is outside the {}x{} atlas grid",
+                index, columns, rows
+            ),
+            Issue::EmptyGrid {
+                tile_size,
+                columns,
+                rows,
+            } => write!(
+                f,
+                "atlas grid of {}x{} cells of {}px is empty",
+                columns, rows, tile_size
+            ),
+        }
+    }
+}
+
+// Checks a loaded tileset, which can only have problems with its edges and weights
+pub fn validate(tileset: &Tileset) -> Vec<Issue> {
+    let tiles = tileset.tiles().cloned().collect::<Vec<_>>();
+    check(&tiles, tileset.columns, tileset.rows)
+}
+
+// Checks a tileset file, reporting everything Tileset::from_ron would stop at and more
+pub fn validate_ron(bytes: &[u8]) -> Result<Vec<Issue>, TilesetError> {
+    let file: TilesetFile = ron::de::from_bytes(bytes)?;
+    if file.tiles.is_empty() {
+        return Err(TilesetError::Empty);
+    }
+    let mut issues = vec![];
+    if file.tile_size == 0 || file.columns == 0 || file.rows == 0 {
+        issues.push(Issue::EmptyGrid {
+            tile_size: file.tile_size,
+            columns: file.columns,
+            rows: file.rows,
+        });
+    }
+    issues.extend(check(&file.tiles, file.columns, file.rows));
+    Ok(issues)
+}
+
+fn check(tiles: &[Tile], columns: u32, rows: u32) -> Vec<Issue> {
+    let mut issues = vec![];
+
+    let mut seen = HashSet::new();
+    for tile in tiles {
+        let index = tile.index;
+        if index >= columns as usize * rows as usize {
+            issues.push(Issue::OutsideAtlas {
+                index,
+                columns,
+                rows,
+            });
+        }
+        if !seen.insert(index) {
+            issues.push(Issue::DuplicateIndex { index });
+        }
+        if !tile.weight.is_finite() || tile.weight < 0.0 {
+            issues.push(Issue::InvalidWeight {
+                index,
+                weight: tile.weight,
+            });
+        } else if tile.weight == 0.0 {
+            issues.push(Issue::ZeroWeight { index });
+        }
+    }
+
+    // turning or mirroring the tile the way its symmetry ignores must not move its edges
+    let edges = |tile: &Tile| DIRECTIONS.map(|direction| direction.edge(tile));
+    for tile in tiles {
+        let wrong = Orientation::all().any(|orientation| {
+            tile.variant(orientation).is_some_and(|variant| {
+                edges(&tile.oriented(orientation)) != edges(&tile.oriented(variant))
+            })
+        });
+        if wrong {
+            issues.push(Issue::WrongSymmetry { index: tile.index });
+        }
+    }
+
+    let indexes = tiles.iter().map(|tile| tile.index).collect::<HashSet<_>>();
+    for tile in tiles {
+        let listed = |adjacent: &Adjacent| match adjacent {
+            Adjacent::Tile(index) => Some(*index),
+            Adjacent::Kind(_) => None,
+        };
+        let unknown = DIRECTIONS
+            .into_iter()
+            .flat_map(|direction| {
+                let neighbours = tile
+                    .neighbours
+                    .as_ref()
+                    .map(|neighbours| neighbours.side(direction));
+                neighbours
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|neighbour| neighbour.index)
+                    .chain(tile.allow.side(direction).iter().filter_map(listed))
+                    .chain(tile.deny.side(direction).iter().filter_map(listed))
+            })
+            .filter(|index| !indexes.contains(index))
+            .collect::<BTreeSet<_>>();
+        for neighbour in unknown {
+            issues.push(Issue::UnknownNeighbour {
+                index: tile.index,
+                neighbour,
+            });
+        }
+    }
+
+    let (tiles, orientations): (Vec<Tile>, Vec<Orientation>) = tiles
+        .iter()
+        .flat_map(|tile| {
+            tile.orientations()
+                .into_iter()
+                .map(|orientation| (tile.oriented(orientation), orientation))
+        })
+        .unzip();
+    let fits = |tile: usize, direction: Direction, other: usize| {
+        direction.fits(
+            (&tiles[tile], orientations[tile]),
+            (&tiles[other], orientations[other]),
+        )
+    };
+    let mut unmatched = HashSet::new();
+    for (i, tile) in tiles.iter().enumerate() {
+        for direction in DIRECTIONS {
+            if !(0..tiles.len()).any(|other| fits(i, direction, other)) {
+                unmatched.insert(i);
+                issues.push(Issue::UnmatchedEdge {
+                    index: tile.index,
+                    orientation: orientations[i],
+                    direction,
+                    edge: direction.edge(tile),
+                });
+            }
+        }
+    }
+
+    // take out the tiles missing a neighbour on some side until all the ones left have four,
+    // like the solver does before the first collapse
+    let mut placeable = vec![true; tiles.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..tiles.len() {
+            if !placeable[i] {
+                continue;
+            }
+            let stuck = DIRECTIONS.into_iter().any(|direction| {
+                !(0..tiles.len()).any(|other| placeable[other] && fits(i, direction, other))
+            });
+            if stuck {
+                placeable[i] = false;
+                changed = true;
+            }
+        }
+    }
+    for (i, tile) in tiles.iter().enumerate() {
+        if !placeable[i] && !unmatched.contains(&i) {
+            issues.push(Issue::Unreachable {
+                index: tile.index,
+                orientation: orientations[i],
+            });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tileset::{Sides, Symmetry, plain_tile};
+
+    use TileKind::*;
+
+    const GRASS: [(TileKind, TileKind); 4] = [(Grass, Grass); 4];
+
+    // Edges in top, bottom, left, right order
+    fn tile(index: usize, weight: f32, edges: [(TileKind, TileKind); 4]) -> Tile {
+        let [top, bottom, left, right] = edges;
+        Tile {
+            index,
+            kind: Grass,
+            weight,
+            top,
+            bottom,
+            left,
+            right,
+            symmetry: None,
+            neighbours: None,
+            allow: Sides::default(),
+            deny: Sides::default(),
+        }
+    }
+
+    #[test]
+    fn accepts_fitting_tiles() {
+        let tiles = [tile(0, 1.0, GRASS), tile(1, 1.0, GRASS)];
+        assert_eq!(check(&tiles, 10, 10), vec![]);
+    }
+
+    #[test]
+    fn reports_unmatched_edges() {
+        let mut edges = GRASS;
+        edges[0] = (Water, Water);
+        let tiles = [tile(0, 1.0, GRASS), tile(1, 1.0, edges)];
+        assert_eq!(
+            check(&tiles, 10, 10),
+            vec![Issue::UnmatchedEdge {
+                index: 1,
+                orientation: Orientation::default(),
+                direction: Direction::Top,
+                edge: (Water, Water),
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_tiles_only_fitting_unplaceable_ones() {
+        // 2 is the only tile that fits above 1, but nothing fits left of 2
+        let mut below = GRASS;
+        below[0] = (Road, Road);
+        let mut above = GRASS;
+        above[1] = (Road, Road);
+        above[2] = (Water, Water);
+        let tiles = [
+            tile(0, 1.0, GRASS),
+            tile(1, 1.0, below),
+            tile(2, 1.0, above),
+        ];
+        let issues = check(&tiles, 10, 10);
+        let unreachable = |index| Issue::Unreachable {
+            index,
+            orientation: Orientation::default(),
+        };
+        assert!(issues.contains(&unreachable(1)));
+        assert!(!issues.contains(&unreachable(2)));
+    }
+
+    #[test]
+    fn reports_wrong_symmetry() {
+        let mut edges = GRASS;
+        edges[2] = (Road, Road);
+        let mut tiles = [tile(0, 1.0, GRASS), tile(1, 1.0, edges)];
+        tiles[1].symmetry = Some(Symmetry::T);
+        assert!(check(&tiles, 10, 10).contains(&Issue::WrongSymmetry { index: 1 }));
+        tiles[1].symmetry = Some(Symmetry::F);
+        assert!(!check(&tiles, 10, 10).contains(&Issue::WrongSymmetry { index: 1 }));
+    }
+
+    #[test]
+    fn honors_allow_and_deny_lists() {
+        let mut tiles = [tile(0, 1.0, GRASS), tile(1, 1.0, GRASS)];
+        tiles[0].deny.top = vec![Adjacent::Kind(Grass)];
+        tiles[1].allow.left = vec![Adjacent::Tile(9)];
+        let issues = check(&tiles, 10, 10);
+        let unmatched = |index, direction| Issue::UnmatchedEdge {
+            index,
+            orientation: Orientation::default(),
+            direction,
+            edge: (Grass, Grass),
+        };
+        assert!(issues.contains(&unmatched(0, Direction::Top)));
+        assert!(issues.contains(&unmatched(1, Direction::Left)));
+        assert!(!issues.contains(&unmatched(0, Direction::Bottom)));
+        assert!(issues.contains(&Issue::UnknownNeighbour {
+            index: 1,
+            neighbour: 9
+        }));
+    }
+
+    #[test]
+    fn reports_file_problems() {
+        let tiles = [
+            tile(0, 0.0, GRASS),
+            tile(0, -1.0, GRASS),
+            tile(100, 1.0, GRASS),
+        ];
+        let issues = check(&tiles, 10, 10);
+        assert!(issues.contains(&Issue::ZeroWeight { index: 0 }));
+        assert!(issues.contains(&Issue::InvalidWeight {
+            index: 0,
+            weight: -1.0
+        }));
+        assert!(issues.contains(&Issue::DuplicateIndex { index: 0 }));
+        assert!(issues.contains(&Issue::OutsideAtlas {
+            index: 100,
+            columns: 10,
+            rows: 10
+        }));
+    }
+
+    #[test]
+    fn reports_empty_grids() {
+        let text = format!(
+            "(atlas: \"tiles.png\", tile_size: 0, columns: 10, rows: 10, tiles: [{}])",
+            plain_tile(0, "Grass", "")
+        );
+        let issues = validate_ron(text.as_bytes()).unwrap();
+        let empty = Issue::EmptyGrid {
+            tile_size: 0,
+            columns: 10,
+            rows: 10,
+        };
+        assert_eq!(issues, vec![empty.clone()]);
+        assert!(empty.is_error());
+    }
+}
@@ -1,12 +1,21 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future};
+use clap::Parser;
 
-mod map;
-use map::{
-    MAP_WIDTH,
-    MAP_HEIGHT,
-    TILE_SIZE,
-    generate_map
+use wfc::checks::{RegionSize, RoadNetworks};
+use wfc::map::{
+    Border, Check, GenerationError, GenerationOptions, Generator, Heuristic, Map, MinCount,
+    Progress, Scanline, Shannon, Step, Surroundings, generate_map_with_options,
 };
+use wfc::render::render_map_to_png;
+use wfc::save::SavedMap;
+use wfc::tiled::{atlas_path, export_tmj, import_tmj};
+use wfc::tileset::{Orientation, TileKind, Tileset, TilesetLoader};
 
 #[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
 struct Position {
@@ -24,27 +33,245 @@ struct SelectedTile;
 #[require(Camera2d)]
 struct MainCamera;
 
+#[derive(Component)]
+struct LoadingText;
+
+#[derive(States, Default, Clone, PartialEq, Eq, Hash, Debug)]
+enum GameState {
+    // Waiting for the tileset or for the map to be generated
+    #[default]
+    Loading,
+    Playing,
+}
+
+#[derive(Resource, Parser)]
+struct MapConfig {
+    /// Map width in tiles
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
+    /// Map height in tiles
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    height: u16,
+    /// Size of a tile in world units
+    #[arg(long, default_value_t = 32.0, value_parser = parse_tile_size)]
+    tile_size: f32,
+    /// Generate the same map every run, random when left out
+    #[arg(long)]
+    seed: Option<u64>,
+    /// How the generator picks the next cell to collapse
+    #[arg(long, value_enum, default_value_t = HeuristicKind::Shannon)]
+    heuristic: HeuristicKind,
+    /// Tile kind surrounding the whole map, e.g. water for an island
+    #[arg(long, conflicts_with = "infinite")]
+    border: Option<TileKind>,
+    /// Generate parts of the map again until its roads form at most this many networks
+    #[arg(long, conflicts_with_all = ["visualize", "infinite"])]
+    road_networks: Option<usize>,
+    /// Generate parts of the map again until every region of a kind has a size in range, as
+    /// kind:min..max with either bound optional, e.g. water:6.. (repeatable)
+    #[arg(long, conflicts_with_all = ["visualize", "infinite"])]
+    region_size: Vec<RegionSize>,
+    /// Watch the generator work step by step: space pauses, period steps while paused,
+    /// +/- change the speed
+    #[arg(long, conflicts_with = "infinite")]
+    visualize: bool,
+    /// Endless map generated in chunks around the camera, width and height are ignored
+    #[arg(long)]
+    infinite: bool,
+    /// Chunk width and height in tiles for --infinite
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..))]
+    chunk_size: u16,
+    /// File the map is saved to with F5 and loaded from with F9
+    #[arg(long, default_value = "map.save.ron")]
+    save_file: PathBuf,
+    /// Tiled map the map is exported to with F6 and imported from with F8
+    #[arg(long, default_value = "map.tmj")]
+    tiled_file: PathBuf,
+    /// Write the generated map to this PNG file and exit without opening a window
+    #[arg(long)]
+    render: Option<PathBuf>,
+}
+
+impl MapConfig {
+    fn generation_options(&self, seed: u64) -> GenerationOptions {
+        GenerationOptions {
+            width: self.width as usize,
+            height: self.height as usize,
+            seed,
+            heuristic: self.heuristic.heuristic(),
+            border: self.border.map(Border::uniform).unwrap_or_default(),
+            checks: self
+                .road_networks
+                .map(|max| Arc::new(RoadNetworks { max }) as Arc<dyn Check>)
+                .into_iter()
+                .chain(
+                    self.region_size
+                        .iter()
+                        .map(|size| Arc::new(size.clone()) as Arc<dyn Check>),
+                )
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+// Chunks and tiles are placed by dividing by the tile size, so it has to be a real size
+fn parse_tile_size(value: &str) -> Result<f32, String> {
+    let size = value.parse::<f32>().map_err(|err| err.to_string())?;
+    if !size.is_finite() || size <= 0.0 {
+        return Err(format!("{} is not a size above 0", size));
+    }
+    Ok(size)
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum HeuristicKind {
+    Shannon,
+    MinCount,
+    Scanline,
+}
+
+impl HeuristicKind {
+    fn heuristic(self) -> Arc<dyn Heuristic> {
+        match self {
+            HeuristicKind::Shannon => Arc::new(Shannon),
+            HeuristicKind::MinCount => Arc::new(MinCount),
+            HeuristicKind::Scanline => Arc::new(Scanline),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MapAssets {
+    tileset: Handle<Tileset>,
+    // Loaded once the tileset is known, kept here so edits to it can be detected
+    atlas: Option<Handle<Image>>,
+    layout: Option<Handle<TextureAtlasLayout>>,
+}
+
+// The finished map, kept so it can be saved
+#[derive(Resource)]
+struct CurrentMap {
+    seed: u64,
+    tiles: Map,
+}
+
+// A map being generated on the AsyncComputeTaskPool
+#[derive(Resource)]
+struct MapGeneration {
+    task: Task<Result<Map, GenerationError>>,
+    progress: Progress,
+    max_repairs: usize,
+    seed: u64,
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
+// A map being generated on the main thread a few steps per frame, for --visualize
+#[derive(Resource)]
+struct Visualizer {
+    generator: Generator,
+    seed: u64,
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    // Size in world units of the tiles and placeholders
+    tile_size: f32,
+    paused: bool,
+    steps_per_second: f32,
+    // Steps owed from previous frames, so slow speeds still advance
+    pending_steps: f32,
+}
+
+// Chunks of the --infinite map, generated as the camera gets close and dropped when it's far
+#[derive(Resource)]
+struct ChunkWorld {
+    tileset: Tileset,
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    seed: u64,
+    chunks: HashMap<IVec2, Chunk>,
+    // Failed generations of chunks not there yet, each retry gets another seed
+    attempts: HashMap<IVec2, u32>,
+}
+
+struct Chunk {
+    tiles: Map,
+    entities: Vec<Entity>,
+}
+
+// Chunks further than this from the view are despawned
+const CHUNK_KEEP_DISTANCE: i32 = 2;
+// Keeps frames short when a lot of chunks are missing, the rest wait for the next frames
+const CHUNKS_PER_FRAME: usize = 8;
+// A chunk failing this many times is left empty until the camera goes far from it
+const CHUNK_ATTEMPTS: u32 = 10;
+
+const TILESET_PATH: &str = "tiles.tileset.ron";
+
 const RESOLUTION_X: f32 = 1024.0;
 const RESOLUTION_Y: f32 = 1024.0;
 
 fn main() {
+    let config = MapConfig::parse();
+    if let Some(output) = &config.render {
+        if let Err(err) = render_headless(&config, output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .insert_resource(ClearColor(Color::srgb(0.04, 0.04, 0.04)))
-        .add_systems(Startup, (setup, spawn_tiles))
+        .insert_resource(config)
+        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest())) // prevents blurry sprites
+        .init_asset::<Tileset>()
+        .init_asset_loader::<TilesetLoader>()
+        .init_state::<GameState>()
+        .enable_state_scoped_entities::<GameState>()
+        .add_systems(Startup, setup)
+        .add_systems(OnEnter(GameState::Loading), spawn_loading_text)
         .add_systems(
             Update,
             (
+                start_generation,
+                spawn_tiles,
+                (visualizer_controls, step_visualizer).chain(),
+                stream_chunks,
+                update_loading_text.run_if(in_state(GameState::Loading)),
                 move_camera,
                 position_tiles,
                 position_markers,
                 mouse_coordinates,
+                save_and_load_map,
+                export_and_import_tiled,
             ),
         )
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest())) // prevents blurry sprites
         .run();
 }
 
-fn setup(mut commands: Commands, mut window: Single<&mut Window>) {
+// Generates a map and renders it straight from the atlas files, without Bevy
+fn render_headless(config: &MapConfig, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let assets = Path::new("assets");
+    let tileset = Tileset::from_ron(&std::fs::read(assets.join(TILESET_PATH))?)?;
+    let seed = config.seed.unwrap_or_else(rand::random);
+    println!("Generating map with seed {}", seed);
+    let map = generate_map_with_options(&tileset, &config.generation_options(seed))?;
+    render_map_to_png(&tileset, &assets.join(&tileset.atlas), &map, output)?;
+    println!("Map rendered to {}", output.display());
+    Ok(())
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut window: Single<&mut Window>,
+) {
+    commands.insert_resource(MapAssets {
+        tileset: asset_server.load(TILESET_PATH),
+        atlas: None,
+        layout: None,
+    });
     commands.spawn((
         MainCamera,
         Transform::from_xyz(RESOLUTION_X / 2.0, RESOLUTION_Y / 2.0, 0.0),
@@ -52,51 +279,712 @@ fn setup(mut commands: Commands, mut window: Single<&mut Window>) {
     window.resolution.set(RESOLUTION_X, RESOLUTION_Y);
 }
 
-fn spawn_tiles(
+fn spawn_loading_text(mut commands: Commands) {
+    commands.spawn((
+        Text::new("Loading tileset..."),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        },
+        LoadingText,
+        StateScoped(GameState::Loading),
+    ));
+}
+
+fn update_loading_text(
+    generation: Option<Res<MapGeneration>>,
+    mut text: Single<&mut Text, With<LoadingText>>,
+) {
+    let Some(generation) = generation else {
+        return;
+    };
+    let progress = &generation.progress;
+    text.0 = match progress.repairs() {
+        0 => format!("Generating map... {:.0}%", progress.fraction() * 100.0),
+        repairs => format!(
+            "Repairing map... {} of at most {}",
+            repairs, generation.max_repairs
+        ),
+    };
+}
+
+// Starts (re)generating the map whenever the tileset finishes loading or the tileset or atlas change on disk
+#[allow(clippy::too_many_arguments)]
+fn start_generation(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut map_assets: ResMut<MapAssets>,
+    mut tileset_events: EventReader<AssetEvent<Tileset>>,
+    mut atlas_events: EventReader<AssetEvent<Image>>,
+    tiles: Query<Entity, With<MapTile>>,
+    config: Res<MapConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
 ) {
-    let texture: Handle<Image> = asset_server.load("tiles.png");
-    let layout = TextureAtlasLayout::from_grid(UVec2::splat(32), 10, 10, None, None);
+    // every event is read, those left unread would start another generation next frame
+    let tileset_changed = tileset_events
+        .read()
+        .filter(|event| {
+            event.is_loaded_with_dependencies(&map_assets.tileset)
+                || event.is_modified(&map_assets.tileset)
+        })
+        .count()
+        > 0;
+    let atlas_changed = atlas_events
+        .read()
+        .filter(|event| {
+            map_assets
+                .atlas
+                .as_ref()
+                .is_some_and(|atlas| event.is_modified(atlas))
+        })
+        .count()
+        > 0;
+    if !tileset_changed && !atlas_changed {
+        return;
+    }
+    let Some(tileset) = tilesets.get(&map_assets.tileset) else {
+        return;
+    };
+
+    for tile in &tiles {
+        commands.entity(tile).despawn();
+    }
+
+    let texture: Handle<Image> = asset_server.load(&tileset.atlas);
+    map_assets.atlas = Some(texture.clone());
+    let layout = TextureAtlasLayout::from_grid(
+        UVec2::splat(tileset.tile_size),
+        tileset.columns,
+        tileset.rows,
+        None,
+        None,
+    );
     let texture_atlas_layout = texture_atlas_layouts.add(layout);
+    map_assets.layout = Some(texture_atlas_layout.clone());
+    commands.remove_resource::<CurrentMap>();
+
+    let seed = config.seed.unwrap_or_else(rand::random);
+    info!("Generating map with seed {}", seed);
+    let options = config.generation_options(seed);
+
+    if config.infinite {
+        // every tile was just despawned, chunks come back as the camera sees them
+        commands.remove_resource::<MapGeneration>();
+        commands.insert_resource(ChunkWorld {
+            tileset: tileset.clone(),
+            texture,
+            layout: texture_atlas_layout,
+            seed,
+            chunks: HashMap::new(),
+            attempts: HashMap::new(),
+        });
+        next_state.set(GameState::Playing);
+        return;
+    }
+
+    if config.visualize {
+        commands.remove_resource::<MapGeneration>();
+        let generator = match Generator::new(tileset, &options) {
+            Ok(generator) => generator,
+            Err(err) => {
+                error!("Map generation failed: {}", err);
+                return;
+            }
+        };
+        // placeholders for every cell, step_visualizer keeps them up to date
+        for x in 0..config.width {
+            for y in 0..config.height {
+                commands
+                    .spawn(Sprite::default())
+                    .insert(Position {
+                        x: x as i32,
+                        y: y as i32,
+                    })
+                    .insert(MapTile);
+            }
+        }
+        commands.insert_resource(Visualizer {
+            generator,
+            seed,
+            texture,
+            layout: texture_atlas_layout,
+            tile_size: config.tile_size,
+            paused: false,
+            steps_per_second: 120.0,
+            pending_steps: 0.0,
+        });
+        next_state.set(GameState::Playing);
+        return;
+    }
+
+    let progress = options.progress.clone();
+    let max_repairs = options.max_repairs;
+    let tileset = tileset.clone();
+    // Replacing a generation still running drops its task, which cancels it
+    let task = AsyncComputeTaskPool::get()
+        .spawn(async move { generate_map_with_options(&tileset, &options) });
+
+    commands.insert_resource(MapGeneration {
+        task,
+        progress,
+        max_repairs,
+        seed,
+        texture,
+        layout: texture_atlas_layout,
+    });
+    next_state.set(GameState::Loading);
+}
+
+// Spawns the tiles once the generation task is done
+fn spawn_tiles(
+    mut commands: Commands,
+    generation: Option<ResMut<MapGeneration>>,
+    config: Res<MapConfig>,
+    mut text: Query<&mut Text, With<LoadingText>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(mut generation) = generation else {
+        return;
+    };
+    let Some(result) = block_on(future::poll_once(&mut generation.task)) else {
+        return;
+    };
+    commands.remove_resource::<MapGeneration>();
+
+    let map = match result {
+        Ok(map) => map,
+        Err(err) => {
+            error!("Map generation failed: {}", err);
+            for mut text in &mut text {
+                text.0 = format!("Map generation failed: {}", err);
+            }
+            return;
+        }
+    };
+
+    spawn_map(
+        &mut commands,
+        &generation.texture,
+        &generation.layout,
+        config.tile_size,
+        &map,
+    );
+    commands.insert_resource(CurrentMap {
+        seed: generation.seed,
+        tiles: map,
+    });
+    next_state.set(GameState::Playing);
+}
+
+fn spawn_map(
+    commands: &mut Commands,
+    texture: &Handle<Image>,
+    layout: &Handle<TextureAtlasLayout>,
+    tile_size: f32,
+    map: &Map,
+) {
+    for (x, y, index) in map.iter() {
+        commands
+            .spawn(tile_sprite(
+                texture,
+                layout,
+                tile_size,
+                index,
+                map.orientation(x, y),
+            ))
+            .insert(Position {
+                x: x as i32,
+                y: y as i32,
+            })
+            .insert(MapTile);
+    }
+}
+
+// A tile of the atlas drawn with the orientation of its variant, scaled to fill a cell
+fn tile_sprite(
+    texture: &Handle<Image>,
+    layout: &Handle<TextureAtlasLayout>,
+    tile_size: f32,
+    index: usize,
+    orientation: Orientation,
+) -> (Sprite, Transform) {
+    let mut sprite = Sprite::from_atlas_image(
+        texture.clone(), // TODO find a way to not use clone
+        TextureAtlas {
+            layout: layout.clone(),
+            index,
+        },
+    );
+    sprite.custom_size = Some(Vec2::splat(tile_size));
+    sprite.flip_x = orientation.mirrored;
+    // turns are clockwise, rotations counter clockwise
+    let rotation = Quat::from_rotation_z(-(orientation.turns as f32) * FRAC_PI_2);
+    (sprite, Transform::from_rotation(rotation))
+}
 
-    let map = generate_map();
-
-    for x in 0..MAP_WIDTH {
-        for y in 0..MAP_HEIGHT {
-            commands
-                .spawn(Sprite::from_atlas_image(
-                    texture.clone(), // TODO find a way to not use clone
-                    TextureAtlas {
-                        layout: texture_atlas_layout.clone(),
-                        index: map[x][y],
-                    },
-                ))
-                .insert(Position {
-                    x: x as i32,
-                    y: y as i32,
-                })
-                .insert(MapTile);
+// F5 saves the current map to the save file, F9 replaces it with the one in the save file
+#[allow(clippy::too_many_arguments)]
+fn save_and_load_map(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    current: Option<Res<CurrentMap>>,
+    map_assets: Res<MapAssets>,
+    tilesets: Res<Assets<Tileset>>,
+    tiles: Query<Entity, With<MapTile>>,
+    mut config: ResMut<MapConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        let Some(current) = current else {
+            warn!("No finished map to save");
+            return;
+        };
+        let saved = SavedMap::new(TILESET_PATH, current.seed, &current.tiles);
+        match saved.save(&config.save_file) {
+            Ok(()) => info!("Map saved to {}", config.save_file.display()),
+            Err(err) => error!("Map could not be saved: {}", err),
         }
     }
+
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        if config.infinite {
+            warn!("Loading a map is not supported with --infinite");
+            return;
+        }
+        let (Some(tileset), Some(texture), Some(layout)) = (
+            tilesets.get(&map_assets.tileset),
+            &map_assets.atlas,
+            &map_assets.layout,
+        ) else {
+            warn!("The tileset is not loaded yet");
+            return;
+        };
+        let loaded = SavedMap::load(&config.save_file)
+            .and_then(|saved| saved.map(tileset).map(|map| (saved, map)));
+        let (saved, map) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!("Map could not be loaded: {}", err);
+                return;
+            }
+        };
+        if saved.tileset != TILESET_PATH {
+            error!(
+                "Map was saved with tileset {}, not {}",
+                saved.tileset, TILESET_PATH
+            );
+            return;
+        }
+
+        info!(
+            "Loaded {}x{} map with seed {} from {}",
+            saved.width,
+            saved.height,
+            saved.seed,
+            config.save_file.display()
+        );
+        replace_map(
+            &mut commands,
+            &tiles,
+            texture,
+            layout,
+            &mut config,
+            saved.seed,
+            map,
+        );
+        next_state.set(GameState::Playing);
+    }
 }
 
-fn position_tiles(mut q: Query<(&Position, &mut Transform), With<MapTile>>) {
+// F6 exports the current map to the Tiled file, F8 replaces it with the one in the Tiled file
+#[allow(clippy::too_many_arguments)]
+fn export_and_import_tiled(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    current: Option<Res<CurrentMap>>,
+    map_assets: Res<MapAssets>,
+    tilesets: Res<Assets<Tileset>>,
+    tiles: Query<Entity, With<MapTile>>,
+    mut config: ResMut<MapConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(tileset) = tilesets.get(&map_assets.tileset) else {
+        return;
+    };
+
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        let Some(current) = current else {
+            warn!("No finished map to export");
+            return;
+        };
+        let atlas = Path::new("assets").join(&tileset.atlas);
+        let result = atlas_path(&atlas, Some(&config.tiled_file))
+            .map_err(|err| err.to_string())
+            .and_then(|image| {
+                export_tmj(
+                    tileset,
+                    &current.tiles,
+                    current.seed,
+                    &image.to_string_lossy(),
+                )
+                .map_err(|err| err.to_string())
+            })
+            .and_then(|tmj| std::fs::write(&config.tiled_file, tmj).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => info!("Map exported to {}", config.tiled_file.display()),
+            Err(err) => error!("Map could not be exported: {}", err),
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        if config.infinite {
+            warn!("Importing a map is not supported with --infinite");
+            return;
+        }
+        let (Some(texture), Some(layout)) = (&map_assets.atlas, &map_assets.layout) else {
+            warn!("The tileset is not loaded yet");
+            return;
+        };
+        let result = std::fs::read_to_string(&config.tiled_file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| import_tmj(&text, tileset).map_err(|err| err.to_string()));
+        let (map, seed) = match result {
+            Ok(imported) => imported,
+            Err(err) => {
+                error!("Map could not be imported: {}", err);
+                return;
+            }
+        };
+
+        info!("Imported map from {}", config.tiled_file.display());
+        // maps drawn from scratch in Tiled have no seed
+        let seed = seed.unwrap_or_default();
+        replace_map(
+            &mut commands,
+            &tiles,
+            texture,
+            layout,
+            &mut config,
+            seed,
+            map,
+        );
+        next_state.set(GameState::Playing);
+    }
+}
+
+// Shows the given map instead of the current one, or of the one being generated
+fn replace_map(
+    commands: &mut Commands,
+    tiles: &Query<Entity, With<MapTile>>,
+    texture: &Handle<Image>,
+    layout: &Handle<TextureAtlasLayout>,
+    config: &mut MapConfig,
+    seed: u64,
+    map: Map,
+) {
+    commands.remove_resource::<MapGeneration>();
+    commands.remove_resource::<Visualizer>();
+    for tile in tiles {
+        commands.entity(tile).despawn();
+    }
+    spawn_map(commands, texture, layout, config.tile_size, &map);
+    config.width = map.width() as u16;
+    config.height = map.height() as u16;
+    commands.insert_resource(CurrentMap { seed, tiles: map });
+}
+
+fn visualizer_controls(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    visualizer: Option<ResMut<Visualizer>>,
+) {
+    let Some(mut visualizer) = visualizer else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        visualizer.paused = !visualizer.paused;
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) && visualizer.paused {
+        visualizer.pending_steps += 1.0;
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        visualizer.steps_per_second = (visualizer.steps_per_second * 2.0).min(10000.0);
+        info!("Visualizer at {} steps per second", visualizer.steps_per_second);
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        visualizer.steps_per_second = (visualizer.steps_per_second / 2.0).max(1.0);
+        info!("Visualizer at {} steps per second", visualizer.steps_per_second);
+    }
+}
+
+// Advances the generator and shows each cell as its tile, or as a grey placeholder
+// that gets darker as fewer tiles are left
+fn step_visualizer(
+    mut commands: Commands,
+    time: Res<Time>,
+    visualizer: Option<ResMut<Visualizer>>,
+    mut tiles: Query<(&Position, &mut Sprite, &mut Transform), With<MapTile>>,
+) {
+    let Some(mut visualizer) = visualizer else {
+        return;
+    };
+    if !visualizer.paused {
+        visualizer.pending_steps += time.delta_secs() * visualizer.steps_per_second;
+    }
+    if visualizer.pending_steps < 1.0 {
+        return;
+    }
+
+    while visualizer.pending_steps >= 1.0 {
+        visualizer.pending_steps -= 1.0;
+        match visualizer.generator.step() {
+            Ok(Step::Done) => break,
+            Ok(_) => {}
+            Err(err) => {
+                error!("Map generation failed: {}", err);
+                visualizer.pending_steps = 0.0;
+                visualizer.paused = true;
+                break;
+            }
+        }
+    }
+
+    let generator = &visualizer.generator;
+    for (pos, mut sprite, mut transform) in &mut tiles {
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        (*sprite, transform.rotation) = match generator.tile(x, y) {
+            Some((index, orientation)) => {
+                let (sprite, placed) = tile_sprite(
+                    &visualizer.texture,
+                    &visualizer.layout,
+                    visualizer.tile_size,
+                    index,
+                    orientation,
+                );
+                (sprite, placed.rotation)
+            }
+            None => {
+                let shade = generator.cell(x, y).count as f32 / generator.tile_count() as f32;
+                let sprite = Sprite::from_color(
+                    Color::srgb(shade, shade, shade),
+                    Vec2::splat(visualizer.tile_size),
+                );
+                (sprite, Quat::IDENTITY)
+            }
+        };
+    }
+
+    // only there once the generator is done
+    if let Some(tiles) = visualizer.generator.map() {
+        info!("Map generated");
+        commands.insert_resource(CurrentMap {
+            seed: visualizer.seed,
+            tiles,
+        });
+        commands.remove_resource::<Visualizer>();
+    }
+}
+
+// Generates the chunks in and around the view and despawns the ones far from it
+fn stream_chunks(
+    mut commands: Commands,
+    world: Option<ResMut<ChunkWorld>>,
+    camera: Single<&Transform, With<MainCamera>>,
+    config: Res<MapConfig>,
+) {
+    let Some(mut world) = world else {
+        return;
+    };
+    let chunk_size = config.chunk_size as i32;
+    let chunk_world_size = chunk_size as f32 * config.tile_size;
+    let to_chunk = |position: Vec2| (position / chunk_world_size).floor().as_ivec2();
+    let half_view = Vec2::new(RESOLUTION_X, RESOLUTION_Y) / 2.0;
+    let center = camera.translation.truncate();
+    let camera_chunk = to_chunk(center);
+    // one chunk of margin so they're ready before they scroll into view
+    let min = to_chunk(center - half_view) - IVec2::ONE;
+    let max = to_chunk(center + half_view) + IVec2::ONE;
+
+    let far = world
+        .chunks
+        .keys()
+        .filter(|coords| {
+            coords.cmplt(min - CHUNK_KEEP_DISTANCE).any()
+                || coords.cmpgt(max + CHUNK_KEEP_DISTANCE).any()
+        })
+        .copied()
+        .collect::<Vec<_>>();
+    for coords in far {
+        despawn_chunk(&mut commands, &mut world, coords);
+    }
+    world.attempts.retain(|coords, _| {
+        coords.cmpge(min - CHUNK_KEEP_DISTANCE).all()
+            && coords.cmple(max + CHUNK_KEEP_DISTANCE).all()
+    });
+
+    // closest first
+    let mut missing = (min.x..=max.x)
+        .flat_map(|x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+        .filter(|coords| !world.chunks.contains_key(coords))
+        .filter(|coords| world.attempts.get(coords).copied().unwrap_or(0) < CHUNK_ATTEMPTS)
+        .collect::<Vec<_>>();
+    missing.sort_by_key(|coords| (*coords - camera_chunk).abs().max_element());
+
+    for coords in missing.into_iter().take(CHUNKS_PER_FRAME) {
+        let result = generate_chunk(&world, &config, coords);
+        if result.is_err() {
+            let attempts = world.attempts.entry(coords).or_default();
+            *attempts += 1;
+            if *attempts == CHUNK_ATTEMPTS {
+                warn!(
+                    "Chunk {} is left empty after {} attempts",
+                    coords, CHUNK_ATTEMPTS
+                );
+            }
+        }
+        match result {
+            Ok(tiles) => {
+                let entities = spawn_chunk(&mut commands, &world, config.tile_size, coords, &tiles);
+                world.chunks.insert(coords, Chunk { tiles, entities });
+                world.attempts.remove(&coords);
+            }
+            Err(
+                GenerationError::Contradiction { x, y, .. }
+                | GenerationError::Unsatisfiable { x, y, .. },
+            ) => {
+                // the neighbours' edges can't be joined, drop the one furthest from the camera
+                // touching the cell and let both chunks be generated again
+                let size = config.chunk_size as usize - 1;
+                let sides = [
+                    (x == 0, IVec2::NEG_X),
+                    (x == size, IVec2::X),
+                    (y == 0, IVec2::NEG_Y),
+                    (y == size, IVec2::Y),
+                ];
+                let neighbour = sides
+                    .into_iter()
+                    .filter(|(touches, _)| *touches)
+                    .map(|(_, offset)| coords + offset)
+                    .filter(|neighbour| world.chunks.contains_key(neighbour))
+                    .max_by_key(|neighbour| (*neighbour - camera_chunk).abs().max_element());
+                match neighbour {
+                    Some(neighbour) => despawn_chunk(&mut commands, &mut world, neighbour),
+                    None => error!("Chunk {} could not be generated", coords),
+                }
+            }
+            Err(err) => error!("Chunk {} could not be generated: {}", coords, err),
+        }
+    }
+}
+
+// Generates a chunk whose sides fit the neighbouring chunks already there
+fn generate_chunk(
+    world: &ChunkWorld,
+    config: &MapConfig,
+    coords: IVec2,
+) -> Result<Map, GenerationError> {
+    let size = config.chunk_size as usize;
+    let neighbour = |offset: IVec2| {
+        world
+            .chunks
+            .get(&(coords + offset))
+            .map(|chunk| &chunk.tiles)
+    };
+    let placed = |tiles: &Map, x, y| (tiles[(x, y)], tiles.orientation(x, y));
+    // the row or column of each neighbour that touches this chunk
+    let surroundings = Surroundings {
+        top: neighbour(IVec2::Y)
+            .map(|tiles| (0..size).map(|x| placed(tiles, x, 0)).collect())
+            .unwrap_or_default(),
+        bottom: neighbour(IVec2::NEG_Y)
+            .map(|tiles| (0..size).map(|x| placed(tiles, x, size - 1)).collect())
+            .unwrap_or_default(),
+        left: neighbour(IVec2::NEG_X)
+            .map(|tiles| (0..size).map(|y| placed(tiles, size - 1, y)).collect())
+            .unwrap_or_default(),
+        right: neighbour(IVec2::X)
+            .map(|tiles| (0..size).map(|y| placed(tiles, 0, y)).collect())
+            .unwrap_or_default(),
+    };
+
+    // the same chunk for the same --seed and neighbours, which neighbours are there depends on
+    // where the camera went. A retry after a failure mixes in the attempt to get another chunk.
+    let attempt = world.attempts.get(&coords).copied().unwrap_or(0) as u64;
+    let seed = world.seed
+        ^ ((coords.x as u32 as u64) << 32 | coords.y as u32 as u64)
+        ^ attempt.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let options = GenerationOptions {
+        width: size,
+        height: size,
+        surroundings,
+        ..config.generation_options(seed)
+    };
+    generate_map_with_options(&world.tileset, &options)
+}
+
+fn spawn_chunk(
+    commands: &mut Commands,
+    world: &ChunkWorld,
+    tile_size: f32,
+    coords: IVec2,
+    tiles: &Map,
+) -> Vec<Entity> {
+    let size = tiles.width() as i32;
+    let mut entities = vec![];
+    for (x, y, index) in tiles.iter() {
+        let entity = commands
+            .spawn(tile_sprite(
+                &world.texture,
+                &world.layout,
+                tile_size,
+                index,
+                tiles.orientation(x, y),
+            ))
+            .insert(Position {
+                x: coords.x * size + x as i32,
+                y: coords.y * size + y as i32,
+            })
+            .insert(MapTile)
+            .id();
+        entities.push(entity);
+    }
+    entities
+}
+
+fn despawn_chunk(commands: &mut Commands, world: &mut ChunkWorld, coords: IVec2) {
+    if let Some(chunk) = world.chunks.remove(&coords) {
+        for entity in chunk.entities {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn position_tiles(
+    mut q: Query<(&Position, &mut Transform), With<MapTile>>,
+    config: Res<MapConfig>,
+) {
+    let tile_size = config.tile_size;
     for (pos, mut transform) in q.iter_mut() {
         transform.translation = Vec3::new(
-            pos.x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-            pos.y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+            pos.x as f32 * tile_size + tile_size / 2.0,
+            pos.y as f32 * tile_size + tile_size / 2.0,
             0.0,
         );
     }
 }
 
-fn position_markers(mut q: Query<(&Position, &mut Transform), With<SelectedTile>>) {
+fn position_markers(
+    mut q: Query<(&Position, &mut Transform), With<SelectedTile>>,
+    config: Res<MapConfig>,
+) {
+    let tile_size = config.tile_size;
     for (pos, mut transform) in q.iter_mut() {
         transform.translation = Vec3::new(
-            pos.x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
-            pos.y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+            pos.x as f32 * tile_size + tile_size / 2.0,
+            pos.y as f32 * tile_size + tile_size / 2.0,
             1.0,
         );
     }
@@ -107,8 +995,10 @@ fn mouse_coordinates(
     window: Single<&Window>,
     camera_query: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
     mut tile_sprites: Query<(&mut Sprite, &Position), With<MapTile>>,
+    config: Res<MapConfig>,
 ) {
     let (camera, camera_transform) = *camera_query;
+    let tile_size = config.tile_size;
 
     if !buttons.just_pressed(MouseButton::Left) && !buttons.pressed(MouseButton::Left) {
         return;
@@ -122,10 +1012,10 @@ fn mouse_coordinates(
         tile_sprites
             .iter_mut()
             .filter(|(_sprite, pos)| {
-                pos.x as f32 * TILE_SIZE <= world_position.x
-                    && world_position.x < (pos.x as f32 + 1.0) * TILE_SIZE
-                    && pos.y as f32 * TILE_SIZE <= world_position.y
-                    && world_position.y < (pos.y as f32 + 1.0) * TILE_SIZE
+                pos.x as f32 * tile_size <= world_position.x
+                    && world_position.x < (pos.x as f32 + 1.0) * tile_size
+                    && pos.y as f32 * tile_size <= world_position.y
+                    && world_position.y < (pos.y as f32 + 1.0) * tile_size
             })
             .for_each(|(mut sprite, pos)| {
                 sprite.color = Color::BLACK;
@@ -137,6 +1027,7 @@ fn mouse_coordinates(
 fn move_camera(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     mut query: Query<&mut Transform, With<Camera2d>>,
+    config: Res<MapConfig>,
 ) {
     let mut direction = Vec3::ZERO;
     if keyboard_input.pressed(KeyCode::ArrowUp) {
@@ -155,14 +1046,28 @@ fn move_camera(
     for mut transform in &mut query {
         // TODO Add time
         transform.translation += direction;
+        if config.infinite {
+            continue;
+        }
         // clamp translation to map bounds
-        transform.translation.x = transform.translation.x.clamp(
-            RESOLUTION_X / 2.0,
-            MAP_WIDTH as f32 * TILE_SIZE - RESOLUTION_X / 2.0,
+        transform.translation.x = clamp_to_map(
+            transform.translation.x,
+            RESOLUTION_X,
+            config.width as f32 * config.tile_size,
         );
-        transform.translation.y = transform.translation.y.clamp(
-            RESOLUTION_Y / 2.0,
-            MAP_HEIGHT as f32 * TILE_SIZE - RESOLUTION_Y / 2.0,
+        transform.translation.y = clamp_to_map(
+            transform.translation.y,
+            RESOLUTION_Y,
+            config.height as f32 * config.tile_size,
         );
     }
 }
+
+// Keeps the view inside the map, or centered on it when the map is smaller than the view
+fn clamp_to_map(position: f32, view_size: f32, map_size: f32) -> f32 {
+    if map_size <= view_size {
+        map_size / 2.0
+    } else {
+        position.clamp(view_size / 2.0, map_size - view_size / 2.0)
+    }
+}